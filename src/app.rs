@@ -1,4 +1,6 @@
+use std::collections::HashSet;
 use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
 use std::process::{Command, Stdio};
 use std::sync::{Arc, mpsc};
 use std::sync::mpsc::Receiver;
@@ -9,8 +11,16 @@ use rand::{
     distributions::{Distribution, Uniform},
     rngs::ThreadRng,
 };
-use ratatui::widgets::ListState;
+use ratatui::crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Margin, Rect};
+use ratatui::widgets::{ListState, TableState};
 use serde::Deserialize;
+use crate::asn::AsnLookup;
+use crate::ixp::IxpDb;
+use crate::places::nearest_city;
+use crate::geo::{great_circle_point, wrapped_range};
+use crate::gradient::Gradient;
+use crate::projection::{angular_distance, ProjectionKind, View};
 use crate::DATA_TYPE;
 
 pub struct TabsState<'a> {
@@ -79,23 +89,175 @@ impl<T> StatefulList<T> {
 
 pub struct TraceEntry {
     pub no: String,
+    pub hop: usize,
     pub ip: String,
     pub name: String,
     pub time: String,
     pub lat: f32,
     pub long: f32,
+    pub asn: Option<u32>,
+    pub as_name: String,
+    pub ixp: Option<String>,
+    /// Feature of the country layer the hop is located in
+    pub country: Option<usize>,
+    pub city: String,
+    /// ISO country code, from the country layer when it has codes, else the nearest city's
+    pub cc: String,
+    pub geo_source: Option<GeoSource>,
+    /// ICMP annotations traceroute printed after the probe, e.g. `!H`
+    pub annotations: Vec<String>,
+    /// The traceroute output line the hop was parsed from
+    pub raw: String,
+}
+
+/// Where a hop's location came from
+pub enum GeoSource {
+    IpApi,
+    Ixp { facilities: usize, radius_km: f32 },
+}
+
+impl GeoSource {
+    /// Source and accuracy, for people
+    pub fn describe(&self) -> (String, String) {
+        match self {
+            GeoSource::IpApi => (
+                "ip-api.com".to_string(),
+                "city level at best, often the operator's registered address".to_string(),
+            ),
+            GeoSource::Ixp { facilities, radius_km } => (
+                "PeeringDB exchange facilities".to_string(),
+                format!("centre of {facilities} facilities, furthest {radius_km:.0} km away"),
+            ),
+        }
+    }
+}
+
+/// What traceroute's ICMP annotations mean
+pub fn describe_annotation(annotation: &str) -> String {
+    match annotation {
+        "!H" => "host unreachable".to_string(),
+        "!N" => "network unreachable".to_string(),
+        "!P" => "protocol unreachable".to_string(),
+        "!S" => "source route failed".to_string(),
+        "!F" => "fragmentation needed".to_string(),
+        "!X" => "communication administratively prohibited".to_string(),
+        "!V" => "host precedence violation".to_string(),
+        "!C" => "precedence cutoff in effect".to_string(),
+        code => match code.strip_prefix('!').and_then(|c| c.parse::<u8>().ok()) {
+            Some(code) => format!("ICMP unreachable code {code}"),
+            None => "unknown".to_string(),
+        },
+    }
+}
+
+impl TraceEntry {
+    pub fn rtt(&self) -> Option<f32> {
+        self.time.split(' ').next()?.parse().ok()
+    }
+
+    /// "City, CC", or as much of it as is known
+    pub fn place(&self) -> String {
+        match (self.city.is_empty(), self.cc.is_empty()) {
+            (false, false) => format!("{}, {}", self.city, self.cc),
+            (false, true) => self.city.clone(),
+            (true, false) => self.cc.clone(),
+            (true, true) => "-".to_string(),
+        }
+    }
+}
+
+/// Run of consecutive entries originated by the same AS
+pub struct AsGroup {
+    pub asn: Option<u32>,
+    pub name: String,
+    pub entries: Range<usize>,
+    pub hops: usize,
+    pub entry_rtt: Option<f32>,
+    pub exit_rtt: Option<f32>,
+}
+
+pub enum AsRow {
+    Group(usize),
+    Entry(usize),
+}
+
+/// How the countries along the route are shaded
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CountryFill {
+    Off,
+    HopOrder,
+    Latency,
+}
+
+impl CountryFill {
+    pub fn next(self) -> CountryFill {
+        match self {
+            CountryFill::Off => CountryFill::HopOrder,
+            CountryFill::HopOrder => CountryFill::Latency,
+            CountryFill::Latency => CountryFill::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CountryFill::Off => "off",
+            CountryFill::HopOrder => "by hop order",
+            CountryFill::Latency => "by latency",
+        }
+    }
+}
+
+/// A country the route passes through, with the first entry located in it
+pub struct Traversed {
+    pub country: usize,
+    pub entry: usize,
+    pub rtt: Option<f32>,
+}
+
+/// Display options from the command line
+pub struct Settings {
+    pub tick_rate: Duration,
+    pub enhanced_graphics: bool,
+    /// Graticule lines, built once at the spacing asked for
+    pub graticule: Vec<Vec<(f32, f32)>>,
+    /// Marker colours by RTT, and link colours by the RTT added across the link
+    pub rtt_colours: Gradient,
+    pub rtt_increase_colours: Gradient,
 }
 
 pub struct App<'a> {
     pub title: &'a str,
     pub should_quit: bool,
     pub tabs: TabsState<'a>,
-    pub enhanced_graphics: bool,
+    pub settings: Settings,
     pub show_countries: bool,
+    pub show_graticule: bool,
+    /// Equator, tropics and antimeridian
+    pub show_reference_lines: bool,
+    pub country_fill: CountryFill,
+    pub servers_state: TableState,
+    pub show_hop_detail: bool,
+    pub as_view: bool,
+    pub as_view_state: TableState,
+    pub expanded_as: HashSet<usize>,
+    pub projection: ProjectionKind,
+    pub globe_rot: (f32, f32),
     pub zoom: f32,
     pub map_pos: (f32, f32),
+    /// Whether the map keeps the whole route in view as hops arrive
+    pub follow_route: bool,
+    /// How many ms into the route's latency the animated pulse is, `None` when the animation is off
+    pub pulse: Option<f32>,
+    /// Where the map was last drawn, border included
+    pub map_area: Rect,
+    /// Last cell the left button was held over, and whether it has moved since going down
+    pub drag: Option<(u16, u16)>,
+    pub dragged: bool,
     pub data_countries: DATA_TYPE,
     pub data_world: DATA_TYPE,
+    pub layers: Vec<DATA_TYPE>,
+    pub asn_lookup: AsnLookup,
+    pub ixp_db: Option<Arc<IxpDb>>,
     pub input: String,
     pub active_trace: Option<Receiver<TraceEntry>>,
     pub trace_target: Option<String>,
@@ -106,17 +268,35 @@ pub struct App<'a> {
 }
 
 impl<'a> App<'a> {
-    pub fn new(title: &'a str, enhanced_graphics: bool, data_countries: DATA_TYPE, data_world: DATA_TYPE) -> Self {
+    pub fn new(title: &'a str, settings: Settings, data_countries: DATA_TYPE, data_world: DATA_TYPE, layers: Vec<DATA_TYPE>, asn_lookup: AsnLookup, ixp_db: Option<Arc<IxpDb>>) -> Self {
         App {
             title,
             should_quit: false,
             tabs: TabsState::new(vec!["Main"]),
-            enhanced_graphics,
+            settings,
             show_countries: false,
+            show_graticule: false,
+            show_reference_lines: false,
+            country_fill: CountryFill::Off,
+            servers_state: TableState::default(),
+            show_hop_detail: false,
+            as_view: false,
+            as_view_state: TableState::default(),
+            expanded_as: HashSet::new(),
+            projection: ProjectionKind::Equirectangular,
+            globe_rot: (0.0, 20.0),
             zoom: 1.0,
             map_pos: (0.5, 0.5),
+            follow_route: true,
+            pulse: None,
+            map_area: Rect::default(),
+            drag: None,
+            dragged: false,
             data_countries,
             data_world,
+            layers,
+            asn_lookup,
+            ixp_db,
             input: String::new(),
             active_trace: None,
             trace_target: None,
@@ -135,14 +315,343 @@ impl<'a> App<'a> {
         self.tabs.previous();
     }
 
+    pub fn rotate_globe(&mut self, long: f32, lat: f32) {
+        self.follow_route = false;
+        let (l, la) = self.globe_rot;
+        self.globe_rot = (
+            (l + long / self.zoom + 540.0) % 360.0 - 180.0,
+            (la + lat / self.zoom).clamp(-90.0, 90.0),
+        );
+    }
+
     pub fn on_key(&mut self, c: char) {
         self.input.push(c);
     }
 
+    /// Multiplies the zoom, keeping the map under `anchor` (a canvas position, e.g. the mouse cursor)
+    /// or else the selected hop where it is
+    pub fn zoom_by(&mut self, factor: f32, anchor: Option<(f32, f32)>) {
+        self.follow_route = false;
+        let anchor = anchor.or_else(|| self.selected_hop_point());
+        self.set_zoom(self.zoom * factor, anchor);
+    }
+
+    /// Moves the map by a fraction of the canvas
+    pub fn pan(&mut self, x: f32, y: f32) {
+        self.follow_route = false;
+        self.map_pos = (self.map_pos.0 + x / self.zoom, self.map_pos.1 + y / self.zoom);
+    }
+
+    /// Zooms and pans (or turns the globe) so every geolocated hop is in view
+    pub fn fit_route(&mut self) {
+        let located = self.trace_result.iter()
+            .filter(|t| !t.lat.is_nan() && !t.long.is_nan())
+            .map(|t| (t.long, t.lat))
+            .collect_vec();
+        if located.is_empty() { return; }
+
+        if self.projection == ProjectionKind::Orthographic {
+            let longs = located.iter().map(|p| p.0).collect_vec();
+            let Some((west, east)) = wrapped_range(&longs, 360.0) else { return; };
+            let (south, north) = located.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+            let centre = (((west + east) / 2.0 + 180.0).rem_euclid(360.0) - 180.0, (south + north) / 2.0);
+
+            // A point d degrees from the centre lies sin(d) of the globe's radius out
+            let furthest = located.iter().map(|p| angular_distance(*p, centre)).fold(0.0f32, f32::max);
+            self.globe_rot = centre;
+            self.zoom = if furthest >= 90.0 { 1.0 } else { (FIT_MARGIN / furthest.to_radians().sin()).clamp(1.0, FIT_MAX_ZOOM) };
+            return;
+        }
+
+        let view = View::new(self, self.map_area);
+        let points = located.iter().filter_map(|(long, lat)| view.projection.project(*long, *lat)).collect_vec();
+        let xs = points.iter().map(|p| p.0).collect_vec();
+        let Some((left, right)) = wrapped_range(&xs, 1.0) else { return; };
+        let (top, bottom) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+
+        let extent = (right - left).max(bottom - top).max(1.0 / FIT_MAX_ZOOM);
+        self.zoom = (FIT_MARGIN / extent).clamp(1.0, FIT_MAX_ZOOM);
+        self.map_pos = (((left + right) / 2.0).rem_euclid(1.0), ((top + bottom) / 2.0).clamp(0.0, 1.0));
+    }
+
+    /// Zooms keeping the map under `anchor`, a canvas position, where it is
+    pub fn set_zoom(&mut self, zoom: f32, anchor: Option<(f32, f32)>) {
+        let old = self.zoom;
+        self.zoom = zoom.clamp(1.0, MAX_ZOOM);
+
+        // The globe stays centred in its canvas
+        if self.projection == ProjectionKind::Orthographic { return; }
+        let Some((x, y)) = anchor else { return; };
+        let shift = 1.0 / old - 1.0 / self.zoom;
+        self.map_pos = (self.map_pos.0 + (x - 0.5) * shift, self.map_pos.1 + (0.5 - y) * shift);
+    }
+
+    /// Canvas position of the selected hop, on the copy of the world nearest the middle of the map
+    fn selected_hop_point(&self) -> Option<(f32, f32)> {
+        let t = self.trace_result.get(self.servers_state.selected()?)?;
+        let view = View::new(self, self.map_area);
+        let off_centre = |(x, y): &(f32, f32)| (x - 0.5).abs() + (y - 0.5).abs();
+        view.copies()
+            .filter_map(|copy| view.to_canvas(copy, t.long, t.lat))
+            .min_by(|a, b| off_centre(a).total_cmp(&off_centre(b)))
+    }
+
+    /// Located hops, each with the latency the route has built up by reaching it
+    fn pulse_route(&self) -> Vec<((f32, f32), f32)> {
+        let mut reached = 0.0f32;
+        self.trace_result.iter()
+            .filter(|t| !t.lat.is_nan())
+            .map(|t| {
+                reached = reached.max(t.rtt().unwrap_or(0.0));
+                ((t.long, t.lat), reached)
+            })
+            .collect()
+    }
+
+    /// Where the pulse is along the route, each link taking as long to cross as the latency it adds
+    pub fn pulse_position(&self) -> Option<(f32, f32)> {
+        let pulse = self.pulse?;
+        let route = self.pulse_route();
+        let k = route.iter().rposition(|(_, rtt)| *rtt <= pulse)?;
+        let (from, start) = route[k];
+        let Some(&(to, end)) = route.get(k + 1) else { return Some(from); };
+        Some(great_circle_point(from, to, (pulse - start) / (end - start)))
+    }
+
+    pub fn toggle_pulse(&mut self) {
+        self.pulse = if self.pulse.is_some() { None } else { Some(0.0) };
+    }
+
+    /// Canvas position of a terminal cell, if it's on the map
+    fn canvas_point(&self, column: u16, row: u16) -> Option<(f32, f32)> {
+        let inner = self.map_area.inner(Margin::new(1, 1));
+        if !inner.contains((column, row).into()) {
+            return None;
+        }
+        // Labels and markers land on the cell their position truncates to, so aim for the middle of it
+        Some((
+            ((column - inner.x) as f32 + 0.5) / (inner.width - 1).max(1) as f32,
+            1.0 - ((row - inner.y) as f32 + 0.5) / (inner.height - 1).max(1) as f32,
+        ))
+    }
+
+    pub fn on_mouse(&mut self, mouse: MouseEvent) {
+        let point = self.canvas_point(mouse.column, mouse.row);
+
+        match mouse.kind {
+            MouseEventKind::ScrollUp if point.is_some() => self.zoom_by(ZOOM_STEP, point),
+            MouseEventKind::ScrollDown if point.is_some() => self.zoom_by(1.0 / ZOOM_STEP, point),
+            MouseEventKind::Down(MouseButton::Left) if point.is_some() => {
+                self.drag = Some((mouse.column, mouse.row));
+                self.dragged = false;
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some((column, row)) = self.drag else { return; };
+                let inner = self.map_area.inner(Margin::new(1, 1));
+                let dx = (mouse.column as f32 - column as f32) / inner.width.max(1) as f32;
+                let dy = (mouse.row as f32 - row as f32) / inner.height.max(1) as f32;
+
+                // The map follows the cursor
+                if self.projection == ProjectionKind::Orthographic {
+                    self.rotate_globe(-dx * 180.0, dy * 180.0);
+                }
+                else {
+                    self.pan(-dx, -dy);
+                }
+                self.drag = Some((mouse.column, mouse.row));
+                self.dragged = true;
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                let clicked = self.drag.take().is_some() && !self.dragged;
+                if let Some(point) = point.filter(|_| clicked) {
+                    self.select_marker_at(point);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Selects the hop whose marker is within a cell of a canvas position
+    fn select_marker_at(&mut self, (x, y): (f32, f32)) {
+        let inner = self.map_area.inner(Margin::new(1, 1));
+        let view = View::new(self, self.map_area);
+        let view = &view;
+
+        let nearest = self.trace_result.iter().enumerate()
+            .flat_map(|(i, t)| view.copies().filter_map(move |copy| view.to_canvas(copy, t.long, t.lat).map(|p| (i, p))))
+            .map(|(i, (mx, my))| (i, ((mx - x) * inner.width as f32).abs(), ((my - y) * inner.height as f32).abs()))
+            .filter(|(_, dx, dy)| *dx <= 1.5 && *dy <= 1.0)
+            .min_by(|a, b| (a.1 + a.2).total_cmp(&(b.1 + b.2)));
+        let Some((entry, _, _)) = nearest else { return; };
+
+        self.servers_state.select(Some(entry));
+        if self.as_view {
+            let groups = self.as_groups();
+            let rows = self.as_rows(&groups);
+            let row = rows.iter().position(|r| matches!(r, AsRow::Entry(e) if *e == entry))
+                .or_else(|| rows.iter().position(|r| matches!(r, AsRow::Group(g) if groups[*g].entries.contains(&entry))));
+            self.as_view_state.select(row);
+        }
+    }
+
+    pub fn as_groups(&self) -> Vec<AsGroup> {
+        let mut groups: Vec<AsGroup> = Vec::new();
+
+        for (i, t) in self.trace_result.iter().enumerate() {
+            // Extra probes ("-") and timeouts ("x") carry no AS of their own
+            let responder = t.ip != "-" && t.ip != "x";
+
+            match groups.last_mut() {
+                Some(g) if !responder || g.asn == t.asn => {
+                    if self.trace_result[i - 1].hop != t.hop {
+                        g.hops += 1;
+                    }
+                    g.entries.end = i + 1;
+                    g.exit_rtt = t.rtt().or(g.exit_rtt);
+                    g.entry_rtt = g.entry_rtt.or(t.rtt());
+                }
+                _ => groups.push(AsGroup {
+                    asn: t.asn,
+                    name: t.as_name.clone(),
+                    entries: i..i + 1,
+                    hops: 1,
+                    entry_rtt: t.rtt(),
+                    exit_rtt: t.rtt(),
+                }),
+            }
+        }
+
+        groups
+    }
+
+    pub fn as_rows(&self, groups: &[AsGroup]) -> Vec<AsRow> {
+        let mut rows = Vec::new();
+        for (g, group) in groups.iter().enumerate() {
+            rows.push(AsRow::Group(g));
+            if self.expanded_as.contains(&group.entries.start) {
+                rows.extend(group.entries.clone().map(AsRow::Entry));
+            }
+        }
+        rows
+    }
+
+    /// Countries in the order the route first enters them, with the RTT on arrival
+    pub fn traversed_countries(&self) -> Vec<Traversed> {
+        let mut traversed: Vec<Traversed> = Vec::new();
+        for (i, t) in self.trace_result.iter().enumerate() {
+            let Some(country) = t.country else { continue };
+            match traversed.iter_mut().find(|c| c.country == country) {
+                Some(c) => c.rtt = c.rtt.or(t.rtt()),
+                None => traversed.push(Traversed { country, entry: i, rtt: t.rtt() }),
+            }
+        }
+        traversed
+    }
+
+    pub fn on_as_row_next(&mut self) {
+        let count = self.as_rows(&self.as_groups()).len();
+        if count == 0 { return; }
+        let i = self.as_view_state.selected().map_or(0, |i| (i + 1) % count);
+        self.as_view_state.select(Some(i));
+        self.select_as_row_entry();
+    }
+
+    pub fn on_as_row_previous(&mut self) {
+        let count = self.as_rows(&self.as_groups()).len();
+        if count == 0 { return; }
+        let i = self.as_view_state.selected().map_or(0, |i| (i + count - 1) % count);
+        self.as_view_state.select(Some(i));
+        self.select_as_row_entry();
+    }
+
+    /// Keeps the selected hop on the entry under the AS path cursor, or the first of its group
+    fn select_as_row_entry(&mut self) {
+        let groups = self.as_groups();
+        let entry = match self.as_view_state.selected().and_then(|i| self.as_rows(&groups).into_iter().nth(i)) {
+            Some(AsRow::Entry(e)) => e,
+            Some(AsRow::Group(g)) => groups[g].entries.start,
+            None => return,
+        };
+        self.servers_state.select(Some(entry));
+    }
+
+    pub fn on_hop_next(&mut self) {
+        let count = self.trace_result.len();
+        if count == 0 { return; }
+        let i = self.servers_state.selected().map_or(0, |i| (i + 1) % count);
+        self.servers_state.select(Some(i));
+    }
+
+    pub fn on_hop_previous(&mut self) {
+        let count = self.trace_result.len();
+        if count == 0 { return; }
+        let i = self.servers_state.selected().map_or(0, |i| (i + count - 1) % count);
+        self.servers_state.select(Some(i));
+    }
+
+    /// Opens the detail popup for the selected hop, or closes it
+    pub fn toggle_hop_detail(&mut self) {
+        let selected = self.servers_state.selected().is_some_and(|i| i < self.trace_result.len());
+        self.show_hop_detail = !self.show_hop_detail && selected;
+    }
+
+    /// Pans (or turns the globe) to put the selected hop in the middle of the map
+    pub fn centre_on_selected_hop(&mut self) {
+        let Some(t) = self.servers_state.selected().and_then(|i| self.trace_result.get(i)) else { return; };
+        let (long, lat) = (t.long, t.lat);
+        if long.is_nan() || lat.is_nan() { return; }
+
+        self.follow_route = false;
+        if self.projection == ProjectionKind::Orthographic {
+            self.globe_rot = (long, lat);
+        }
+        else if let Some(pos) = View::new(self, self.map_area).projection.project(long, lat) {
+            self.map_pos = pos;
+        }
+    }
+
+    pub fn toggle_as_row(&mut self) {
+        let groups = self.as_groups();
+        let rows = self.as_rows(&groups);
+        let Some(row) = self.as_view_state.selected().and_then(|i| rows.get(i)) else { return; };
+
+        let group = match row {
+            AsRow::Group(g) => &groups[*g],
+            AsRow::Entry(e) => groups.iter().find(|g| g.entries.contains(e)).unwrap(),
+        };
+        let key = group.entries.start;
+
+        if !self.expanded_as.remove(&key) {
+            self.expanded_as.insert(key);
+        }
+        else {
+            // Keep the cursor on the collapsed group
+            let g = groups.iter().position(|g| g.entries.start == key).unwrap();
+            let row = self.as_rows(&groups).iter().position(|r| matches!(r, AsRow::Group(x) if *x == g));
+            self.as_view_state.select(row);
+        }
+    }
+
     pub fn on_tick(&mut self) {
+        if let Some(pulse) = self.pulse {
+            // Replayed slowed down, with a pause at the destination before starting over
+            let end = self.pulse_route().last().map_or(0.0, |p| p.1);
+            let pulse = pulse + self.settings.tick_rate.as_secs_f32() * 1000.0 / PULSE_SLOWDOWN;
+            self.pulse = Some(if pulse > end * PULSE_PAUSE { 0.0 } else { pulse });
+        }
+
         if let Some(trace) = &mut self.active_trace {
-            if let Ok(data) = trace.try_recv() {
+            if let Ok(mut data) = trace.try_recv() {
+                if !data.lat.is_nan() {
+                    data.country = self.data_countries.feature_at(data.long, data.lat);
+                }
+                if let Some(code) = data.country.map(|c| &self.data_countries.codes[c]).filter(|c| !c.is_empty()) {
+                    data.cc = code.clone();
+                }
                 self.trace_result.push(data);
+                if self.follow_route {
+                    self.fit_route();
+                }
             }
         }
 
@@ -167,9 +676,18 @@ impl<'a> App<'a> {
         self.trace_error = Some(erx);
         self.error = false;
         self.trace_result = Vec::new();
+        self.expanded_as.clear();
+        self.as_view_state.select(None);
+        self.servers_state.select(None);
+        self.show_hop_detail = false;
+        if self.pulse.is_some() {
+            self.pulse = Some(0.0);
+        }
         self.trace_target = Some(self.input.clone());
 
         let target = self.input.clone();
+        let asn_lookup = self.asn_lookup.clone();
+        let ixp_db = self.ixp_db.clone();
 
         self.input = String::new();
 
@@ -198,16 +716,30 @@ impl<'a> App<'a> {
                         let Some(n) = components.next() else {
                             if let Some(ns) = sections.next() {
                                 components = ns.split(" ");
+                                // Sections not starting with a time belong to the next responder
+                                if ns.split(' ').next().and_then(|t| t.parse::<f32>().ok()).is_none() {
+                                    continue;
+                                }
 
                                 let mut time = components.next().unwrap().to_string();
                                 time += " ";
                                 time += components.next().unwrap();
                                 traces.push(TraceEntry {
                                     no: format!("{}", i+1),
+                                    hop: i + 1,
                                     ip: "-".to_string(),
                                     name: "-".to_string(),
                                     lat: f32::NAN,
                                     long: f32::NAN,
+                                    asn: None,
+                                    as_name: String::new(),
+                                    ixp: None,
+                                    country: None,
+                                    city: String::new(),
+                                    cc: String::new(),
+                                    geo_source: None,
+                                    annotations: Vec::new(),
+                                    raw: String::new(),
                                     time
                                 });
                                 continue;
@@ -217,14 +749,32 @@ impl<'a> App<'a> {
                             }
                         };
 
+                        // ICMP annotations follow the time of the probe they belong to
+                        if n.starts_with('!') {
+                            if let Some(trace) = traces.last_mut() {
+                                trace.annotations.push(n.to_string());
+                            }
+                            continue;
+                        }
+
                         if n == "*" {
                             traces.push(TraceEntry {
                                 no: format!("{}", i+1),
+                                hop: i + 1,
                                 ip: "x".to_string(),
                                 name: "x".to_string(),
                                 time: "-".to_string(),
                                 lat: f32::NAN,
                                 long: f32::NAN,
+                                asn: None,
+                                as_name: String::new(),
+                                ixp: None,
+                                country: None,
+                                city: String::new(),
+                                cc: String::new(),
+                                geo_source: None,
+                                annotations: Vec::new(),
+                                raw: String::new(),
                             });
                             continue;
                         }
@@ -251,22 +801,47 @@ impl<'a> App<'a> {
                         }
 
 
-                        let l = reqwest::blocking::get(format!("http://ip-api.com/json/{ip}?fields=lat,lon"))
+                        let mut l = reqwest::blocking::get(format!("http://ip-api.com/json/{ip}?fields=lat,lon"))
                             .and_then(|r| r.json())
                             .unwrap_or( Loc { lat: f32::NAN, lon: f32::NAN } );
 
                         // let l = Loc { lat: f32::NAN, lon: f32::NAN };
 
+                        let asn = asn_lookup.lookup(&ip);
+                        let mut geo_source = (!l.lat.is_nan()).then_some(GeoSource::IpApi);
+
+                        // IXP LANs are placed at the exchange's facilities rather than the geo-DB guess
+                        let ixp = ixp_db.as_ref().and_then(|db| db.lookup(&ip));
+                        if let Some(ixp) = ixp.filter(|ixp| !ixp.lat.is_nan()) {
+                            l = Loc { lat: ixp.lat, lon: ixp.long };
+                            geo_source = Some(GeoSource::Ixp { facilities: ixp.facilities, radius_km: ixp.radius_km });
+                        }
+                        let city = nearest_city(l.lat, l.lon);
+
                         traces.push(TraceEntry {
                             no: format!("{}", i+1),
+                            hop: i + 1,
                             ip,
                             name,
                             time,
                             lat: l.lat,
                             long: l.lon,
+                            asn: asn.as_ref().map(|a| a.asn),
+                            as_name: asn.map(|a| a.name).unwrap_or_default(),
+                            ixp: ixp.map(|ixp| ixp.name.clone()),
+                            country: None,
+                            city: city.as_ref().map(|c| c.name.clone()).unwrap_or_default(),
+                            cc: city.map(|c| c.cc).unwrap_or_default(),
+                            geo_source,
+                            annotations: Vec::new(),
+                            raw: String::new(),
                         });
                     }
 
+                    for trace in traces.iter_mut() {
+                        trace.raw = line.to_string();
+                    }
+
                     if traces.len() > 1 {
                         for (x, trace) in traces.iter_mut().enumerate() {
                             trace.no = format!("{}{}", trace.no, ALPH[x]);
@@ -290,4 +865,16 @@ impl<'a> App<'a> {
     }
 }
 
+/// Each zoom step scales the map by the same factor, however far in it is
+pub const ZOOM_STEP: f32 = 1.5;
+/// Deep enough to tell apart hops within a city
+const MAX_ZOOM: f32 = 1000.0;
+/// Fitting the route leaves a border around it, and doesn't zoom far into a single location
+const FIT_MARGIN: f32 = 0.85;
+const FIT_MAX_ZOOM: f32 = 50.0;
+/// The pulse crosses the route this many times slower than the packets did, and waits at the end
+/// for a fifth of the route's latency
+const PULSE_SLOWDOWN: f32 = 10.0;
+const PULSE_PAUSE: f32 = 1.2;
+
 const ALPH: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
\ No newline at end of file
@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct AsnInfo {
+    pub asn: u32,
+    pub name: String,
+}
+
+#[derive(Clone)]
+pub enum AsnLookup {
+    Disabled,
+    Table(Arc<AsnTable>),
+    Dns(String),
+}
+
+impl AsnLookup {
+    pub fn lookup(&self, ip: &str) -> Option<AsnInfo> {
+        let ip: IpAddr = ip.parse().ok()?;
+        match self {
+            AsnLookup::Disabled => None,
+            AsnLookup::Table(table) => table.lookup(ip),
+            AsnLookup::Dns(resolver) => dns_lookup(resolver, ip),
+        }
+    }
+}
+
+struct AsnRange {
+    start: u128,
+    end: u128,
+    parent: Option<usize>,
+    asn: u32,
+    name: String,
+}
+
+/// Offline ip-to-ASN table, either iptoasn-style ranges
+/// (`start end asn country description`) or MRT-derived prefixes (`prefix/len asn [description]`)
+pub struct AsnTable {
+    ranges: Vec<AsnRange>,
+}
+
+impl AsnTable {
+    pub fn load(path: &str) -> Result<AsnTable, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut ranges = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let columns = line.split('\t').collect::<Vec<_>>();
+            let range = if columns[0].contains('/') {
+                if columns.len() < 2 { continue };
+                let Some((start, end)) = parse_prefix(columns[0]) else { continue };
+                let Ok(asn) = columns[1].parse::<u32>() else { continue };
+                (start, end, asn, columns.get(2).copied().unwrap_or(""))
+            }
+            else {
+                if columns.len() < 3 { continue };
+                let (Ok(start), Ok(end)) = (columns[0].parse::<IpAddr>(), columns[1].parse::<IpAddr>()) else { continue };
+                let Ok(asn) = columns[2].parse::<u32>() else { continue };
+                (ip_key(start), ip_key(end), asn, columns.get(4).copied().unwrap_or(""))
+            };
+
+            // AS 0 marks unrouted space in iptoasn dumps
+            if range.2 == 0 { continue };
+
+            ranges.push(AsnRange {
+                start: range.0,
+                end: range.1,
+                parent: None,
+                asn: range.2,
+                name: range.3.to_string(),
+            });
+        }
+
+        // Sorting by start, widest first, puts every range after any range containing it
+        ranges.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+        let mut stack: Vec<usize> = Vec::new();
+        for i in 0..ranges.len() {
+            while let Some(&top) = stack.last() {
+                if ranges[top].end >= ranges[i].end { break; }
+                stack.pop();
+            }
+            ranges[i].parent = stack.last().copied();
+            stack.push(i);
+        }
+
+        Ok(AsnTable { ranges })
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<AsnInfo> {
+        let key = ip_key(ip);
+        let i = self.ranges.partition_point(|r| r.start <= key);
+        let mut current = i.checked_sub(1);
+
+        // Prefixes nest, so the most specific match is the last range starting
+        // before the address or one of its parents
+        while let Some(c) = current {
+            let range = &self.ranges[c];
+            if range.end >= key {
+                return Some(AsnInfo { asn: range.asn, name: range.name.clone() });
+            }
+            current = range.parent;
+        }
+
+        None
+    }
+}
+
+/// Maps both address families into one key space, IPv4 as IPv4-mapped IPv6
+pub fn ip_key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+pub fn parse_prefix(prefix: &str) -> Option<(u128, u128)> {
+    let (ip, len) = prefix.split_once('/')?;
+    let ip: IpAddr = ip.parse().ok()?;
+    let len: u32 = len.parse().ok()?;
+    let len = match ip {
+        IpAddr::V4(_) if len <= 32 => len + 96,
+        IpAddr::V6(_) if len <= 128 => len,
+        _ => return None,
+    };
+
+    let host_mask = u128::MAX.checked_shr(len).unwrap_or(0);
+    let start = ip_key(ip) & !host_mask;
+    Some((start, start | host_mask))
+}
+
+/// Team Cymru's DNS interface, `origin.asn.cymru.com` for the origin AS then
+/// `asn.cymru.com` for its name
+fn dns_lookup(resolver: &str, ip: IpAddr) -> Option<AsnInfo> {
+    let origin_query = match ip {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            format!("{}.{}.{}.{}.origin.asn.cymru.com", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(ip) => {
+            let nibbles = ip.octets().iter()
+                .flat_map(|b| [b >> 4, b & 0xF])
+                .rev()
+                .map(|n| format!("{n:x}"))
+                .collect::<Vec<_>>();
+            format!("{}.origin6.asn.cymru.com", nibbles.join("."))
+        }
+    };
+
+    // "13335 | 1.1.1.0/24 | AU | apnic | 2011-08-11"
+    let origin = query_txt(resolver, &origin_query)?;
+    let asn = origin.split('|').next()?
+        .split_whitespace().next()?
+        .parse::<u32>().ok()?;
+
+    // "13335 | US | arin | 2010-07-14 | CLOUDFLARENET, US"
+    let name = query_txt(resolver, &format!("AS{asn}.asn.cymru.com"))
+        .and_then(|r| r.rsplit('|').next().map(|n| n.trim().to_string()))
+        .unwrap_or_default();
+
+    Some(AsnInfo { asn, name })
+}
+
+fn query_txt(resolver: &str, name: &str) -> Option<String> {
+    let resolver = match resolver.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, 53),
+        Err(_) => resolver.parse::<SocketAddr>().ok()?,
+    };
+
+    let id: u16 = rand::random();
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.extend_from_slice(&[0, 0, 16, 0, 1]);
+
+    let socket = UdpSocket::bind(if resolver.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }).ok()?;
+    socket.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    socket.connect(resolver).ok()?;
+    socket.send(&packet).ok()?;
+
+    let mut buf = [0u8; 512];
+    let len = socket.recv(&mut buf).ok()?;
+    let response = &buf[..len];
+
+    if len < 12 || response[..2] != id.to_be_bytes() || response[3] & 0x0F != 0 {
+        return None;
+    }
+    let answers = u16::from_be_bytes([response[6], response[7]]);
+
+    let mut pos = skip_name(response, 12)? + 4;
+    for _ in 0..answers {
+        pos = skip_name(response, pos)?;
+        let header = response.get(pos..pos + 10)?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let data_len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        let data = response.get(pos..pos + data_len)?;
+        pos += data_len;
+
+        if record_type != 16 { continue; }
+
+        let mut text = String::new();
+        let mut i = 0;
+        while i < data.len() {
+            let part_len = data[i] as usize;
+            text += &String::from_utf8_lossy(data.get(i + 1..i + 1 + part_len)?);
+            i += 1 + part_len;
+        }
+        return Some(text);
+    }
+
+    None
+}
+
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xC0 == 0xC0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len as usize;
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use memmap2::Mmap;
+use crate::loader::Shapes;
+use crate::paths::cache_dir;
+
+const MAGIC: &[u8; 4] = b"TRMC";
+const VERSION: u32 = 6;
+const HEADER_LEN: usize = 60;
+
+/// Coordinates are stored as integer micro-degrees
+const SCALE: f32 = 1_000_000.0;
+
+/// Length and modification time of a file, so edits invalidate what was built from it
+#[derive(PartialEq, Default)]
+struct Stamp {
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.len.to_le_bytes());
+        data.extend_from_slice(&self.modified_secs.to_le_bytes());
+        data.extend_from_slice(&self.modified_nanos.to_le_bytes());
+    }
+}
+
+/// Identifies the files a cache entry was built from. A shapefile's names and codes come from
+/// the `.dbf` beside it, which is stamped too (all zero when there's none).
+#[derive(PartialEq)]
+struct Source {
+    file: Stamp,
+    dbf: Stamp,
+}
+
+impl Source {
+    fn of(path: &Path) -> Option<Source> {
+        let shapefile = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("shp"));
+        Some(Source {
+            file: Stamp::of(path)?,
+            dbf: if shapefile { Stamp::of(&path.with_extension("dbf")).unwrap_or_default() } else { Stamp::default() },
+        })
+    }
+}
+
+fn cache_file(path: &Path) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(path).ok()?.hash(&mut hasher);
+    Some(cache_dir()?.join(format!("{:016x}.bin", hasher.finish())))
+}
+
+/// Shapes previously cached for `path`, `None` if missing or out of date. The cache is mapped
+/// rather than read, but still decoded into owned shapes; what it saves is parsing the source.
+pub fn read(path: &Path) -> Option<Shapes> {
+    read_from(path, &cache_file(path)?)
+}
+
+fn read_from(path: &Path, cache_file: &Path) -> Option<Shapes> {
+    let source = Source::of(path)?;
+    let file = File::open(cache_file).ok()?;
+    // SAFETY: cache files are only ever replaced by renaming, never written in place
+    let data = unsafe { Mmap::map(&file) }.ok()?;
+
+    let u32_at = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    let u64_at = |at: usize| data.get(at..at + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+
+    if data.get(..4)? != MAGIC || u32_at(4)? != VERSION {
+        return None;
+    }
+    let stamp = |at: usize| Some(Stamp { len: u64_at(at)?, modified_secs: u64_at(at + 8)?, modified_nanos: u32_at(at + 16)? });
+    let cached = Source { file: stamp(8)?, dbf: stamp(28)? };
+    if cached != source {
+        return None;
+    }
+
+    let shape_count = u32_at(48)? as usize;
+    let point_count = u32_at(52)? as usize;
+    let name_count = u32_at(56)? as usize;
+    let offsets_start = HEADER_LEN;
+    let features_start = offsets_start + (shape_count + 1) * 4;
+    let rings_start = features_start + shape_count * 4;
+    let points_start = rings_start + shape_count;
+    let names_start = points_start + point_count * 8;
+    if data.len() < names_start {
+        return None;
+    }
+
+    // Names then codes are length-prefixed UTF-8 after the points
+    let mut strings = Vec::with_capacity(name_count * 2);
+    let mut at = names_start;
+    for _ in 0..name_count * 2 {
+        let len = u32_at(at)? as usize;
+        strings.push(String::from_utf8(data.get(at + 4..at + 4 + len)?.to_vec()).ok()?);
+        at += 4 + len;
+    }
+    let codes = strings.split_off(name_count);
+    let names = strings;
+    if at != data.len() {
+        return None;
+    }
+
+    let features = (0..shape_count)
+        .map(|s| u32_at(features_start + s * 4).map(|f| f as usize).filter(|f| *f < name_count))
+        .collect::<Option<Vec<_>>>()?;
+    let rings = data[rings_start..points_start].iter().map(|r| *r != 0).collect();
+
+    let point = |i: usize| {
+        let at = points_start + i * 8;
+        let long = i32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        let lat = i32::from_le_bytes(data[at + 4..at + 8].try_into().unwrap());
+        (long as f32 / SCALE, lat as f32 / SCALE)
+    };
+
+    let lines = (0..shape_count)
+        .map(|s| {
+            let (start, end) = (u32_at(offsets_start + s * 4)? as usize, u32_at(offsets_start + s * 4 + 4)? as usize);
+            (start <= end && end <= point_count).then(|| (start..end).map(point).collect())
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Shapes { lines, features, rings, names, codes })
+}
+
+pub fn write(path: &Path, shapes: &Shapes) -> Result<(), Box<dyn Error>> {
+    write_to(path, &cache_file(path).ok_or("no cache directory")?, shapes)
+}
+
+fn write_to(path: &Path, cache_file: &Path, shapes: &Shapes) -> Result<(), Box<dyn Error>> {
+    let source = Source::of(path).ok_or("source file has no modification time")?;
+    let lines = &shapes.lines;
+    let point_count = lines.iter().map(|s| s.len()).sum::<usize>();
+
+    let mut data = Vec::with_capacity(HEADER_LEN + (lines.len() * 2 + 1) * 4 + lines.len() + point_count * 8);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    source.file.write(&mut data);
+    source.dbf.write(&mut data);
+    data.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(point_count as u32).to_le_bytes());
+    data.extend_from_slice(&(shapes.names.len() as u32).to_le_bytes());
+
+    let mut offset = 0u32;
+    data.extend_from_slice(&offset.to_le_bytes());
+    for shape in lines {
+        offset += shape.len() as u32;
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    for feature in &shapes.features {
+        data.extend_from_slice(&(*feature as u32).to_le_bytes());
+    }
+    data.extend(shapes.rings.iter().map(|ring| *ring as u8));
+    for (long, lat) in lines.iter().flatten() {
+        data.extend_from_slice(&((long * SCALE).round() as i32).to_le_bytes());
+        data.extend_from_slice(&((lat * SCALE).round() as i32).to_le_bytes());
+    }
+    for name in shapes.names.iter().chain(&shapes.codes) {
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+    }
+
+    // Write then rename so a reader never maps a half-written file
+    fs::create_dir_all(cache_file.parent().unwrap())?;
+    let temp = cache_file.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp, data)?;
+    fs::rename(temp, cache_file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let dir = std::env::temp_dir().join(format!("trace-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("shapes.txt");
+        let cache_file = dir.join("shapes.bin");
+        fs::write(&source, "0 0\n").unwrap();
+
+        // Coordinates exact in both f32 and micro-degrees come back unchanged
+        let shapes = Shapes {
+            lines: vec![vec![(-12.25, 40.5), (3.0, 41.0), (-12.25, 40.5)], vec![(170.5, -60.75), (-179.5, -61.0)]],
+            features: vec![0, 1],
+            rings: vec![true, false],
+            names: vec!["Westland".to_string(), String::new()],
+            codes: vec!["WL".to_string(), String::new()],
+        };
+        write_to(&source, &cache_file, &shapes).unwrap();
+
+        let cached = read_from(&source, &cache_file).unwrap();
+        assert_eq!(cached.lines, shapes.lines);
+        assert_eq!(cached.features, shapes.features);
+        assert_eq!(cached.rings, shapes.rings);
+        assert_eq!(cached.names, shapes.names);
+        assert_eq!(cached.codes, shapes.codes);
+
+        // Editing the source leaves the cache stale
+        fs::write(&source, "0 0\n1 1\n").unwrap();
+        assert!(read_from(&source, &cache_file).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
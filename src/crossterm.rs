@@ -4,6 +4,7 @@ use std::{
     time::{Duration, Instant},
 };
 use std::cmp::max;
+use std::sync::Arc;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     crossterm::{
@@ -14,9 +15,12 @@ use ratatui::{
     terminal::Terminal,
 };
 
-use crate::{app::App, DATA_TYPE, ui};
+use crate::{app::{App, Settings, ZOOM_STEP}, DATA_TYPE, ui};
+use crate::asn::AsnLookup;
+use crate::ixp::IxpDb;
+use crate::projection::ProjectionKind;
 
-pub fn run(tick_rate: Duration, enhanced_graphics: bool, data_countries: DATA_TYPE, data_world: DATA_TYPE) -> Result<(), Box<dyn Error>> {
+pub fn run(settings: Settings, data_countries: DATA_TYPE, data_world: DATA_TYPE, layers: Vec<DATA_TYPE>, asn_lookup: AsnLookup, ixp_db: Option<Arc<IxpDb>>) -> Result<(), Box<dyn Error>> {
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -25,7 +29,8 @@ pub fn run(tick_rate: Duration, enhanced_graphics: bool, data_countries: DATA_TY
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::new("Trace", enhanced_graphics, data_countries, data_world);
+    let tick_rate = settings.tick_rate;
+    let app = App::new("Trace", settings, data_countries, data_world, layers, asn_lookup, ixp_db);
     let res = run_app(&mut terminal, app, tick_rate);
 
     // restore terminal
@@ -55,41 +60,57 @@ fn run_app<B: Backend>(
 
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
         if event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
                     match key.code {
+                        KeyCode::Esc if app.show_hop_detail => app.show_hop_detail = false,
                         KeyCode::Esc => app.should_quit = true,
                         KeyCode::Tab => app.show_countries = !app.show_countries,
-                        KeyCode::Char('[') => {
-                            app.zoom = 1.0f32.max(app.zoom - 1.0);
+                        KeyCode::F(2) => app.as_view = !app.as_view,
+                        KeyCode::F(3) => {
+                            app.projection = app.projection.next();
+                            if app.follow_route { app.fit_route(); }
                         }
-                        KeyCode::Char(']') => {
-                            app.zoom = 20.0f32.min(app.zoom + 1.0);
-                        }
-                        KeyCode::Right => {
-                            app.map_pos = (app.map_pos.0 + (0.2 / app.zoom), app.map_pos.1);
-                        }
-                        KeyCode::Left => {
-                            app.map_pos = (app.map_pos.0 - (0.2 / app.zoom), app.map_pos.1);
-                        }
-                        KeyCode::Up => {
-                            app.map_pos = (app.map_pos.0, app.map_pos.1 - (0.2 / app.zoom));
-                        }
-                        KeyCode::Down => {
-                            app.map_pos = (app.map_pos.0, app.map_pos.1 + (0.2 / app.zoom));
+                        KeyCode::F(4) => app.country_fill = app.country_fill.next(),
+                        KeyCode::F(5) => {
+                            app.follow_route = !app.follow_route;
+                            if app.follow_route { app.fit_route(); }
                         }
+                        KeyCode::F(6) => app.toggle_pulse(),
+                        KeyCode::F(7) => app.show_graticule = !app.show_graticule,
+                        KeyCode::F(8) => app.show_reference_lines = !app.show_reference_lines,
+                        KeyCode::PageDown if app.as_view => app.on_as_row_next(),
+                        KeyCode::PageUp if app.as_view => app.on_as_row_previous(),
+                        KeyCode::PageDown => app.on_hop_next(),
+                        KeyCode::PageUp => app.on_hop_previous(),
+                        KeyCode::Home => app.centre_on_selected_hop(),
+                        KeyCode::Char(' ') if app.as_view => app.toggle_as_row(),
+                        KeyCode::Char('[') => app.zoom_by(1.0 / ZOOM_STEP, None),
+                        KeyCode::Char(']') => app.zoom_by(ZOOM_STEP, None),
+                        KeyCode::Right if app.projection == ProjectionKind::Orthographic => app.rotate_globe(10.0, 0.0),
+                        KeyCode::Left if app.projection == ProjectionKind::Orthographic => app.rotate_globe(-10.0, 0.0),
+                        KeyCode::Up if app.projection == ProjectionKind::Orthographic => app.rotate_globe(0.0, 10.0),
+                        KeyCode::Down if app.projection == ProjectionKind::Orthographic => app.rotate_globe(0.0, -10.0),
+                        KeyCode::Right => app.pan(0.2, 0.0),
+                        KeyCode::Left => app.pan(-0.2, 0.0),
+                        KeyCode::Up => app.pan(0.0, -0.2),
+                        KeyCode::Down => app.pan(0.0, 0.2),
                         KeyCode::Backspace => {
                             if !app.input.is_empty() { app.input = app.input.chars().take(app.input.chars().count() - 1).collect(); }
                         }
+                        KeyCode::Enter if app.input.is_empty() => app.toggle_hop_detail(),
                         KeyCode::Enter => {
                             app.trace()
                         }
                         KeyCode::Char(c) => app.on_key(c),
                         _ => {}
                     }
-                    app.map_pos = (app.map_pos.0.min(1.0).max(0.0), app.map_pos.1.min(1.0).max(0.0));
                 }
+                Event::Mouse(mouse) => app.on_mouse(mouse),
+                _ => {}
             }
+            // Longitude wraps around, latitude stops at the poles
+            app.map_pos = (app.map_pos.0.rem_euclid(1.0), app.map_pos.1.clamp(0.0, 1.0));
         }
         if last_tick.elapsed() >= tick_rate {
             app.on_tick();
@@ -1,21 +1,159 @@
+use std::collections::HashMap;
 use ratatui::prelude::Color;
-use ratatui::widgets::canvas::{MapResolution, Painter, Shape};
+use ratatui::widgets::canvas::{Line, Painter, Shape};
 use crate::DATA_TYPE;
+use crate::projection::View;
+use crate::ui::clip_line;
 
-pub struct CMap {
+pub struct CMap<'a> {
     pub data: DATA_TYPE,
-    pub zoom: f32,
-    pub pos: (f32, f32)
+    pub view: &'a View,
+    pub color: Color,
 }
 
-impl Shape for CMap {
+impl Shape for CMap<'_> {
     fn draw(&self, painter: &mut Painter) {
-        for (x, y) in self.data.iter() {
-            let x = (*x - self.pos.0) * self.zoom + 0.5;
-            let y = (*y - self.pos.1) * self.zoom + 0.5;
-            if let Some((x, y)) = painter.get_point(x as f64, 1.0 - y as f64) {
-                painter.paint(x, y, Color::White);
+        let outline = self.view.projection.outline();
+
+        for copy in self.view.copies() {
+            for p in &outline {
+                let (x, y) = self.view.place(copy, *p);
+                if let Some((x, y)) = painter.get_point(x as f64, y as f64) {
+                    painter.paint(x, y, Color::DarkGray);
+                }
+            }
+
+            for run in self.data.visible(self.view, copy) {
+                let mut last: Option<(f32, f32, f32)> = None;
+                for (long, lat) in run {
+                    let point = self.view.to_canvas(copy, *long, *lat);
+
+                    match (last, point) {
+                        // Lone points, e.g. tiny islands at low detail
+                        (None, Some((x, y))) if run.len() == 1 => {
+                            if let Some((x, y)) = painter.get_point(x as f64, y as f64) {
+                                painter.paint(x, y, self.color);
+                            }
+                        }
+                        // Segments jumping the antimeridian would cross the whole map
+                        (Some((x1, y1, long1)), Some((x2, y2))) if (long - long1).abs() <= 180.0 => {
+                            if let Some((x1, y1, x2, y2)) = clip_line(x1, y1, x2, y2) {
+                                Line {
+                                    x1: x1 as f64,
+                                    y1: y1 as f64,
+                                    x2: x2 as f64,
+                                    y2: y2 as f64,
+                                    color: self.color,
+                                }.draw(painter);
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    last = point.map(|(x, y)| (x, y, *long));
+                }
+            }
+        }
+    }
+}
+
+/// Lines given by points along them, e.g. the graticule
+pub struct Polylines<'a> {
+    pub lines: &'a [Vec<(f32, f32)>],
+    pub view: &'a View,
+    pub color: Color,
+}
+
+impl Shape for Polylines<'_> {
+    fn draw(&self, painter: &mut Painter) {
+        for copy in self.view.copies() {
+            for line in self.lines {
+                let points = line.iter().map(|(long, lat)| self.view.to_canvas(copy, *long, *lat)).collect::<Vec<_>>();
+                for pair in points.windows(2) {
+                    let (Some((x1, y1)), Some((x2, y2))) = (pair[0], pair[1]) else { continue; };
+                    let Some((x1, y1, x2, y2)) = clip_line(x1, y1, x2, y2) else { continue; };
+                    Line {
+                        x1: x1 as f64,
+                        y1: y1 as f64,
+                        x2: x2 as f64,
+                        y2: y2 as f64,
+                        color: self.color,
+                    }.draw(painter);
+                }
+            }
+        }
+    }
+}
+
+type Edge = ((f32, f32), (f32, f32));
+
+/// Solid fill of the features given a colour, e.g. the countries a route passes through
+pub struct FeatureFill<'a> {
+    pub data: DATA_TYPE,
+    pub view: &'a View,
+    pub colours: &'a HashMap<usize, Color>,
+}
+
+impl Shape for FeatureFill<'_> {
+    fn draw(&self, painter: &mut Painter) {
+        if self.colours.is_empty() { return; }
+        let Some((max_x, max_y)) = painter.get_point(1.0, 0.0) else { return; };
+
+        for copy in self.view.copies() {
+            // Edges in painter grid coordinates, gathered per feature so holes and islands fill by the even-odd rule
+            let mut edges: HashMap<usize, Vec<Edge>> = HashMap::new();
+            for (feature, shape) in self.data.shapes(self.view, copy) {
+                if !self.colours.contains_key(&feature) { continue; }
+
+                // Half-hidden outlines (the far side of the globe) can't be closed, so aren't filled
+                let Some(points) = shape.iter()
+                    .map(|(long, lat)| self.view.to_canvas(copy, *long, *lat)
+                        .map(|(x, y)| ((x * max_x as f32, (1.0 - y) * max_y as f32), *long)))
+                    .collect::<Option<Vec<_>>>() else { continue; };
+
+                let feature_edges = edges.entry(feature).or_default();
+                for pair in points.windows(2) {
+                    let ((a, long1), (b, long2)) = (pair[0], pair[1]);
+                    if (long1 - long2).abs() <= 180.0 {
+                        feature_edges.push((a, b));
+                    }
+                }
+            }
+
+            for (feature, mut edges) in edges {
+                let colour = self.colours[&feature];
+                let top = |e: &Edge| e.0.1.min(e.1.1);
+                edges.sort_by(|a, b| top(a).total_cmp(&top(b)));
+                let bottom = edges.iter().fold(f32::MIN, |hi, (a, b)| hi.max(a.1).max(b.1));
+                let rows = edges.first().map_or(0.0, top).max(0.0).ceil() as usize..=bottom.min(max_y as f32).floor() as usize;
+
+                // Scanline fill, tracking only the edges spanning the current row
+                let mut next = 0;
+                let mut active: Vec<Edge> = Vec::new();
+                for row in rows {
+                    let y = row as f32;
+                    while next < edges.len() && top(&edges[next]) <= y {
+                        active.push(edges[next]);
+                        next += 1;
+                    }
+                    active.retain(|(a, b)| a.1.max(b.1) > y);
+
+                    let mut crossings = active.iter()
+                        .filter(|(a, b)| (a.1 > y) != (b.1 > y))
+                        .map(|(a, b)| a.0 + (y - a.1) / (b.1 - a.1) * (b.0 - a.0))
+                        .collect::<Vec<_>>();
+                    crossings.sort_by(|a, b| a.total_cmp(b));
+
+                    for span in crossings.chunks_exact(2) {
+                        let start = span[0].max(0.0).ceil() as usize;
+                        let end = span[1].min(max_x as f32);
+                        if end < 0.0 { continue; }
+                        for x in start..=end.floor() as usize {
+                            painter.paint(x, row, colour);
+                        }
+                    }
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
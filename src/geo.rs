@@ -0,0 +1,96 @@
+pub const EARTH_RADIUS_KM: f32 = 6371.0;
+/// Latitude of the tropics of Cancer and Capricorn
+pub const TROPIC_LAT: f32 = 23.44;
+
+fn to_vector(long: f32, lat: f32) -> [f32; 3] {
+    let (long, lat) = (long.to_radians(), lat.to_radians());
+    [lat.cos() * long.cos(), lat.cos() * long.sin(), lat.sin()]
+}
+
+fn from_vector(v: [f32; 3]) -> (f32, f32) {
+    (v[1].atan2(v[0]).to_degrees(), v[2].clamp(-1.0, 1.0).asin().to_degrees())
+}
+
+/// Points along the great circle from `a` to `b` (long, lat), roughly one every `step` degrees of arc
+pub fn great_circle(a: (f32, f32), b: (f32, f32), step: f32) -> Vec<(f32, f32)> {
+    let (va, vb) = (to_vector(a.0, a.1), to_vector(b.0, b.1));
+    let dot = (va[0] * vb[0] + va[1] * vb[1] + va[2] * vb[2]).clamp(-1.0, 1.0);
+    let d = dot.acos();
+
+    // Coincident or antipodal points have no single great circle between them
+    if d < 1e-4 || d.sin() < 1e-4 {
+        return vec![a, b];
+    }
+
+    let n = (d.to_degrees() / step).ceil().max(1.0) as usize;
+    (0..=n).map(|i| slerp(va, vb, d, i as f32 / n as f32)).collect()
+}
+
+/// The point a fraction `t` of the way along the great circle from `a` to `b`
+pub fn great_circle_point(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    let (va, vb) = (to_vector(a.0, a.1), to_vector(b.0, b.1));
+    let d = (va[0] * vb[0] + va[1] * vb[1] + va[2] * vb[2]).clamp(-1.0, 1.0).acos();
+    if d < 1e-4 || d.sin() < 1e-4 {
+        return if t < 0.5 { a } else { b };
+    }
+    slerp(va, vb, d, t)
+}
+
+fn slerp(va: [f32; 3], vb: [f32; 3], d: f32, t: f32) -> (f32, f32) {
+    let (ka, kb) = (((1.0 - t) * d).sin() / d.sin(), (t * d).sin() / d.sin());
+    from_vector([ka * va[0] + kb * vb[0], ka * va[1] + kb * vb[1], ka * va[2] + kb * vb[2]])
+}
+
+/// Splits a path where it crosses the ±180° meridian, ending one part on the map's edge
+/// and starting the next on the opposite edge
+pub fn split_antimeridian(path: &[(f32, f32)]) -> Vec<Vec<(f32, f32)>> {
+    let mut parts = vec![Vec::new()];
+
+    for (i, &(long, lat)) in path.iter().enumerate() {
+        if let Some(&(prev_long, prev_lat)) = i.checked_sub(1).map(|p| &path[p]) {
+            if (long - prev_long).abs() > 180.0 {
+                let edge = 180f32.copysign(prev_long);
+                let unwrapped = long + 360f32.copysign(prev_long);
+                let t = (edge - prev_long) / (unwrapped - prev_long);
+                let crossing = prev_lat + (lat - prev_lat) * t;
+
+                parts.last_mut().unwrap().push((edge, crossing));
+                parts.push(vec![(-edge, crossing)]);
+            }
+        }
+        parts.last_mut().unwrap().push((long, lat));
+    }
+
+    parts
+}
+
+/// Smallest range covering every value on a circle of the given period, found by leaving out the
+/// widest gap between neighbours. The end may exceed the period when the range wraps around.
+pub fn wrapped_range(values: &[f32], period: f32) -> Option<(f32, f32)> {
+    let mut values = values.iter().map(|v| v.rem_euclid(period)).collect::<Vec<_>>();
+    values.sort_by(|a, b| a.total_cmp(b));
+    let (first, last) = (*values.first()?, *values.last()?);
+
+    // The gap across the wrap point, then those between neighbours
+    let (start, gap) = values.windows(2)
+        .map(|pair| (pair[1], pair[1] - pair[0]))
+        .fold((first, first + period - last), |best, next| if next.1 > best.1 { next } else { best });
+    Some((start, start + period - gap))
+}
+
+/// Points along a meridian, pole to pole
+pub fn meridian(long: f32) -> Vec<(f32, f32)> {
+    (-45..=45).map(|i| (long, i as f32 * 2.0)).collect()
+}
+
+/// Points along a parallel, all the way round
+pub fn parallel(lat: f32) -> Vec<(f32, f32)> {
+    (-90..=90).map(|i| (i as f32 * 2.0, lat)).collect()
+}
+
+/// Meridians and parallels every `spacing` degrees, the poles left out
+pub fn graticule(spacing: f32) -> Vec<Vec<(f32, f32)>> {
+    let meridians = (0..).map(|i| -180.0 + i as f32 * spacing).take_while(|long| *long < 180.0).map(meridian);
+    let parallels = (1..).map(|i| -90.0 + i as f32 * spacing).take_while(|lat| *lat < 90.0).map(parallel);
+    meridians.chain(parallels).collect()
+}
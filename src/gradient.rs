@@ -0,0 +1,77 @@
+use std::str::FromStr;
+use ratatui::style::Color;
+
+/// Colours for values along a scale, blended between stops given as `value:colour` pairs,
+/// e.g. `0:green,50:yellow,150:red`. Colours are ratatui names, `#rrggbb` or palette indices.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    pub stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    pub fn parse(spec: &str) -> Result<Gradient, String> {
+        let mut stops = spec.split(',')
+            .map(|stop| {
+                let (value, colour) = stop.trim().split_once(':')
+                    .ok_or_else(|| format!("gradient stop `{stop}` isn't `value:colour`"))?;
+                let value = value.trim().parse::<f32>().map_err(|e| format!("gradient stop `{stop}`: {e}"))?;
+                let colour = Color::from_str(colour.trim()).map_err(|e| format!("gradient stop `{stop}`: {e}"))?;
+                Ok((value, colour))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if stops.is_empty() {
+            return Err("gradient needs at least one stop".to_string());
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Gradient { stops })
+    }
+
+    pub fn colour(&self, value: f32) -> Color {
+        let i = self.stops.partition_point(|(v, _)| *v <= value);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].1;
+        }
+
+        let ((v1, c1), (v2, c2)) = (self.stops[i - 1], self.stops[i]);
+        // Palette colours without a known RGB value can't be blended, they hold until the next stop
+        let (Some(a), Some(b)) = (rgb(c1), rgb(c2)) else { return c1 };
+        let t = (value - v1) / (v2 - v1);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color::Rgb(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+    }
+
+    pub fn min(&self) -> f32 {
+        self.stops[0].0
+    }
+
+    pub fn max(&self) -> f32 {
+        self.stops[self.stops.len() - 1].0
+    }
+}
+
+/// xterm's defaults for the named colours
+fn rgb(colour: Color) -> Option<(u8, u8, u8)> {
+    Some(match colour {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White => (255, 255, 255),
+        _ => return None,
+    })
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::IpAddr;
+use serde::Deserialize;
+use crate::asn::{ip_key, parse_prefix};
+use crate::geo::EARTH_RADIUS_KM;
+use crate::projection::angular_distance;
+
+#[derive(Deserialize)]
+struct Table<T> {
+    data: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct Ix {
+    id: u32,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct IxLan {
+    id: u32,
+    ix_id: u32,
+}
+
+#[derive(Deserialize)]
+struct IxPfx {
+    ixlan_id: u32,
+    prefix: String,
+}
+
+#[derive(Deserialize)]
+struct IxFac {
+    ix_id: u32,
+    fac_id: u32,
+}
+
+#[derive(Deserialize)]
+struct Fac {
+    id: u32,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+}
+
+/// The subset of a PeeringDB JSON export needed to place IXP LANs
+#[derive(Deserialize)]
+struct Dump {
+    ix: Table<Ix>,
+    ixlan: Table<IxLan>,
+    ixpfx: Table<IxPfx>,
+    #[serde(default)]
+    ixfac: Option<Table<IxFac>>,
+    #[serde(default)]
+    fac: Option<Table<Fac>>,
+}
+
+pub struct Ixp {
+    pub name: String,
+    pub lat: f32,
+    pub long: f32,
+    /// Facilities with a known location, and how far the furthest is from the centre
+    pub facilities: usize,
+    pub radius_km: f32,
+}
+
+pub struct IxpDb {
+    prefixes: Vec<(u128, u128, usize)>,
+    exchanges: Vec<Ixp>,
+}
+
+impl IxpDb {
+    pub fn load(path: &str) -> Result<IxpDb, Box<dyn Error>> {
+        let dump: Dump = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        let facilities = dump.fac.map(|f| f.data).unwrap_or_default().into_iter()
+            .filter_map(|f| Some((f.id, (f.latitude?, f.longitude?))))
+            .collect::<HashMap<_, _>>();
+
+        let mut locations: HashMap<u32, Vec<(f32, f32)>> = HashMap::new();
+        for ixfac in dump.ixfac.map(|f| f.data).unwrap_or_default() {
+            let Some(location) = facilities.get(&ixfac.fac_id) else { continue };
+            locations.entry(ixfac.ix_id).or_default().push(*location);
+        }
+
+        let mut exchange_index = HashMap::new();
+        let mut exchanges = Vec::new();
+        for ix in dump.ix.data {
+            let points = locations.get(&ix.id).map_or(&[][..], |l| &l[..]);
+
+            // An exchange spanning several facilities is placed at their centre
+            let n = points.len() as f32;
+            let (lat, long) = points.iter().fold((0.0, 0.0), |(lat, long), p| (lat + p.0 / n, long + p.1 / n));
+            let radius = points.iter().map(|p| angular_distance((p.1, p.0), (long, lat))).fold(0.0f32, f32::max);
+
+            exchange_index.insert(ix.id, exchanges.len());
+            exchanges.push(Ixp {
+                name: ix.name,
+                lat: if points.is_empty() { f32::NAN } else { lat },
+                long: if points.is_empty() { f32::NAN } else { long },
+                facilities: points.len(),
+                radius_km: radius.to_radians() * EARTH_RADIUS_KM,
+            });
+        }
+
+        let lans = dump.ixlan.data.into_iter()
+            .filter_map(|lan| Some((lan.id, *exchange_index.get(&lan.ix_id)?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut prefixes = dump.ixpfx.data.into_iter()
+            .filter_map(|pfx| {
+                let (start, end) = parse_prefix(&pfx.prefix)?;
+                Some((start, end, *lans.get(&pfx.ixlan_id)?))
+            })
+            .collect::<Vec<_>>();
+        prefixes.sort_by_key(|p| p.0);
+
+        Ok(IxpDb { prefixes, exchanges })
+    }
+
+    pub fn lookup(&self, ip: &str) -> Option<&Ixp> {
+        let key = ip_key(ip.parse::<IpAddr>().ok()?);
+        let i = self.prefixes.partition_point(|p| p.0 <= key).checked_sub(1)?;
+        let (_, end, ix) = self.prefixes[i];
+        (end >= key).then(|| &self.exchanges[ix])
+    }
+}
@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use serde_json::Value;
+use crate::cache;
+use crate::paths::find_data;
+
+/// Polylines grouped into named features, e.g. every ring of one country
+#[derive(Default)]
+pub struct Shapes {
+    pub lines: Vec<Vec<(f32, f32)>>,
+    /// Feature each line belongs to
+    pub features: Vec<usize>,
+    /// Whether each line is a closed outline, as opposed to e.g. a coastline or river
+    pub rings: Vec<bool>,
+    pub names: Vec<String>,
+    /// ISO 3166-1 alpha-2 code of each feature, empty when it has none
+    pub codes: Vec<String>,
+}
+
+impl Shapes {
+    fn feature(&mut self, name: String, code: String) {
+        self.names.push(name);
+        self.codes.push(code);
+    }
+
+    fn push(&mut self, line: Vec<(f32, f32)>, ring: bool) {
+        if self.names.is_empty() {
+            self.feature(String::new(), String::new());
+        }
+        self.features.push(self.names.len() - 1);
+        self.rings.push(ring);
+        self.lines.push(line);
+    }
+}
+
+/// Property names Natural Earth and most other datasets keep feature names under
+const NAME_PROPERTIES: [&str; 4] = ["NAME", "ADMIN", "name", "NAME_EN"];
+/// Natural Earth's ISO_A2 is -99 for a few countries (France, Norway) that ISO_A2_EH has
+const CODE_PROPERTIES: [&str; 4] = ["ISO_A2_EH", "ISO_A2", "iso_a2", "ISO2"];
+
+/// Low resolution coastlines (gnuplotting.org's world_110m, as shipped with ratatui),
+/// used when no map data is installed
+const BUNDLED_WORLD: &str = include_str!("../data/world_110m.txt");
+
+/// Loads the map data at `path`, looked up along `search` when relative. Without a path the
+/// `default` file is used if it can be found, otherwise the bundled world.
+pub fn load_map(path: Option<&str>, default: &str, search: &[PathBuf]) -> Result<Shapes, Box<dyn Error>> {
+    match path {
+        Some(path) => {
+            let found = find_data(path, search).ok_or_else(|| {
+                let dirs = search.iter().map(|d| d.display().to_string()).join(", ");
+                format!("map data {path} not found in {dirs}")
+            })?;
+            load_shapes(&found)
+        }
+        None => match find_data(default, search) {
+            Some(found) => load_shapes(&found),
+            None => parse_text(BUNDLED_WORLD.as_bytes()),
+        },
+    }
+}
+
+/// Loads polylines from `lon lat` text, GeoJSON or an ESRI shapefile, picked by extension,
+/// reusing the binary cache unless the file has changed since it was parsed
+fn load_shapes(path: &Path) -> Result<Shapes, Box<dyn Error>> {
+    if let Some(shapes) = cache::read(path) {
+        return Ok(shapes);
+    }
+
+    let shapes = parse_shapes(path)?;
+    // The cache only speeds up the next start, failing to write it isn't fatal
+    let _ = cache::write(path, &shapes);
+    Ok(shapes)
+}
+
+fn parse_shapes(path: &Path) -> Result<Shapes, Box<dyn Error>> {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("json") | Some("geojson") => load_geojson(path),
+        Some("shp") => load_shapefile(path),
+        _ => parse_text(BufReader::new(File::open(path)?)),
+    }
+}
+
+fn parse_text(reader: impl BufRead) -> Result<Shapes, Box<dyn Error>> {
+    let mut shapes = Shapes::default();
+    let mut shape = Vec::new();
+    // Text shapes can be open coastlines, only those already ending where they start are rings
+    let end_shape = |shapes: &mut Shapes, shape: Vec<(f32, f32)>| {
+        let ring = shape.len() > 2 && shape.first() == shape.last();
+        shapes.feature(String::new(), String::new());
+        shapes.push(shape, ring);
+    };
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        // Blank lines separate shapes, each its own unnamed feature
+        if line.is_empty() {
+            if !shape.is_empty() {
+                end_shape(&mut shapes, std::mem::take(&mut shape));
+            }
+            continue;
+        }
+
+        let numbers = line.split(' ').collect_vec();
+        if numbers.len() < 2 { continue };
+        let Ok(long) = numbers[0].parse::<f32>() else { continue };
+        let Ok(lat) = numbers[1].parse::<f32>() else { continue };
+
+        shape.push((long, lat));
+    }
+    if !shape.is_empty() {
+        end_shape(&mut shapes, shape);
+    }
+
+    Ok(shapes)
+}
+
+fn load_geojson(path: &Path) -> Result<Shapes, Box<dyn Error>> {
+    let json: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let mut shapes = Shapes::default();
+    add_geojson(&json, &mut shapes, false);
+    Ok(shapes)
+}
+
+fn add_geojson(json: &Value, shapes: &mut Shapes, in_feature: bool) {
+    let line = |coordinates: &Value| coordinates.as_array()
+        .map(|points| points.iter()
+            .filter_map(|p| Some((p.get(0)?.as_f64()? as f32, p.get(1)?.as_f64()? as f32)))
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+    let lines = |coordinates: &Value| coordinates.as_array()
+        .map(|lines| lines.iter().map(line).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let coordinates = &json["coordinates"];
+    let geometry_type = json["type"].as_str();
+
+    // Geometries outside a feature are features of their own
+    if !in_feature && !matches!(geometry_type, Some("FeatureCollection") | Some("Feature")) {
+        shapes.feature(String::new(), String::new());
+    }
+
+    match geometry_type {
+        Some("FeatureCollection") => {
+            for feature in json["features"].as_array().into_iter().flatten() {
+                add_geojson(feature, shapes, false);
+            }
+        }
+        Some("Feature") => {
+            let property = |properties: &[&str]| properties.iter()
+                .filter_map(|p| json["properties"][p].as_str())
+                .find(|v| *v != "-99")
+                .unwrap_or_default()
+                .to_string();
+            shapes.feature(property(&NAME_PROPERTIES), property(&CODE_PROPERTIES));
+            add_geojson(&json["geometry"], shapes, true);
+        }
+        Some("GeometryCollection") => {
+            for geometry in json["geometries"].as_array().into_iter().flatten() {
+                add_geojson(geometry, shapes, true);
+            }
+        }
+        Some("LineString") => shapes.push(line(coordinates), false),
+        Some("MultiLineString") => lines(coordinates).into_iter().for_each(|l| shapes.push(l, false)),
+        Some("Polygon") => lines(coordinates).into_iter().for_each(|l| shapes.push(l, true)),
+        Some("MultiPolygon") => {
+            for polygon in coordinates.as_array().into_iter().flatten() {
+                lines(polygon).into_iter().for_each(|l| shapes.push(l, true));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads the PolyLine and Polygon records (with or without Z/M) of a `.shp` file, named from
+/// the `.dbf` beside it when there is one
+fn load_shapefile(path: &Path) -> Result<Shapes, Box<dyn Error>> {
+    let dbf = fs::read(path.with_extension("dbf")).unwrap_or_default();
+    let names = dbf_column(&dbf, &NAME_PROPERTIES).unwrap_or_default();
+    let codes = dbf_column(&dbf, &CODE_PROPERTIES).unwrap_or_default();
+
+    let data = fs::read(path)?;
+    if data.len() < 100 || data[..4] != 9994i32.to_be_bytes() {
+        return Err(format!("{} is not a shapefile", path.display()).into());
+    }
+
+    let i32_le = |at: usize| data.get(at..at + 4).map(|b| i32::from_le_bytes(b.try_into().unwrap()));
+    let f64_le = |at: usize| data.get(at..at + 8).map(|b| f64::from_le_bytes(b.try_into().unwrap()));
+    // Lengths and counts are signed in the file, a negative one means it's corrupt
+    let corrupt = || format!("{} is corrupt", path.display());
+    let count = |value: i32| usize::try_from(value).map_err(|_| corrupt());
+
+    let mut shapes = Shapes::default();
+    let mut pos = 100;
+    let mut record = 0;
+    while pos + 8 <= data.len() {
+        let content_len = count(i32::from_be_bytes(data[pos + 4..pos + 8].try_into().unwrap()))? * 2;
+        let content = pos + 8;
+        pos = content.checked_add(content_len).ok_or_else(corrupt)?;
+
+        let Some(shape_type) = i32_le(content) else { break };
+        record += 1;
+        if ![3, 5, 13, 15, 23, 25].contains(&shape_type) { continue };
+        // PolyLine records are open lines, Polygon records (5, 15, 25) rings
+        let ring = shape_type % 10 == 5;
+        shapes.feature(
+            names.get(record - 1).cloned().unwrap_or_default(),
+            codes.get(record - 1).filter(|c| *c != "-99").cloned().unwrap_or_default(),
+        );
+
+        // Skip the bounding box
+        let (Some(parts), Some(points)) = (i32_le(content + 36), i32_le(content + 40)) else { break };
+        let (parts, points) = (count(parts)?, count(points)?);
+        let points_start = parts.checked_mul(4).and_then(|p| p.checked_add(content + 44)).ok_or_else(corrupt)?;
+        let points_end = points.checked_mul(16).and_then(|p| p.checked_add(points_start)).ok_or_else(corrupt)?;
+        if points_end > data.len() {
+            return Err(corrupt().into());
+        }
+
+        let starts = (0..parts)
+            .map(|p| count(i32_le(content + 44 + p * 4).unwrap_or_default()))
+            .chain([Ok(points)])
+            .collect::<Result<Vec<_>, _>>()?;
+        for (start, end) in starts.into_iter().tuple_windows() {
+            let line = (start..end)
+                .filter_map(|p| {
+                    let at = points_start + p * 16;
+                    Some((f64_le(at)? as f32, f64_le(at + 8)? as f32))
+                })
+                .collect();
+            shapes.push(line, ring);
+        }
+    }
+
+    Ok(shapes)
+}
+
+/// The first of `columns` a dBase table has, one entry per record
+fn dbf_column(dbf: &[u8], columns: &[&str]) -> Option<Vec<String>> {
+    let records = u32::from_le_bytes(dbf.get(4..8)?.try_into().ok()?) as usize;
+    let header_len = u16::from_le_bytes(dbf.get(8..10)?.try_into().ok()?) as usize;
+    let record_len = u16::from_le_bytes(dbf.get(10..12)?.try_into().ok()?) as usize;
+
+    // Field descriptors follow the header, terminated by 0x0D; records start with a deletion flag
+    let mut fields = Vec::new();
+    let mut offset = 1;
+    let mut at = 32;
+    while *dbf.get(at)? != 0x0D {
+        let descriptor = dbf.get(at..at + 32)?;
+        let name = String::from_utf8_lossy(&descriptor[..11]).trim_end_matches('\0').to_string();
+        let len = descriptor[16] as usize;
+        fields.push((name, offset, len));
+        offset += len;
+        at += 32;
+    }
+
+    let (_, field_offset, field_len) = columns.iter()
+        .find_map(|p| fields.iter().find(|(name, _, _)| name == p))?;
+
+    (0..records)
+        .map(|r| {
+            let start = header_len + r * record_len + field_offset;
+            Some(String::from_utf8_lossy(dbf.get(start..start + field_len)?).trim().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_world_closes_only_rings() {
+        let shapes = parse_text(BUNDLED_WORLD.as_bytes()).unwrap();
+        assert!(!shapes.lines.is_empty());
+        for (i, (line, ring)) in shapes.lines.iter().zip(&shapes.rings).enumerate() {
+            let closed = line.first() == line.last();
+            assert_eq!(*ring, closed && line.len() > 2, "shape {i} from {:?} to {:?}", line.first(), line.last());
+        }
+        // Some of the coastlines are open, e.g. the Siberian ones ending on the antimeridian
+        assert!(shapes.rings.iter().any(|ring| !ring));
+    }
+}
@@ -1,26 +1,31 @@
-use std::{error::Error, io, time::Duration};
-use std::cmp::{max, min};
-use std::f32::consts::PI;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::{error::Error, time::Duration};
 use std::rc::Rc;
-use std::time::Instant;
-use ::crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind};
-use ::crossterm::{event, execute};
-use ::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use std::sync::Arc;
 use argh::FromArgs;
-use image::{ImageBuffer, Pixel, Rgb};
-use itertools::Itertools;
-use ratatui::backend::{Backend, CrosstermBackend};
-use ratatui::Terminal;
-use serde::Deserialize;
-use crate::app::App;
+use crate::app::Settings;
+use crate::asn::{AsnLookup, AsnTable};
 use crate::crossterm::run;
+use crate::geo::graticule;
+use crate::gradient::Gradient;
+use crate::ixp::IxpDb;
+use crate::loader::load_map;
+use crate::map_data::MapLayer;
+use crate::paths::data_search_path;
 
 mod app;
+mod asn;
+mod cache;
+mod ixp;
 mod crossterm;
 mod ui;
 mod custom_map;
+mod geo;
+mod gradient;
+mod loader;
+mod map_data;
+mod paths;
+mod places;
+mod projection;
 
 /// Demo
 #[derive(Debug, FromArgs)]
@@ -31,146 +36,77 @@ struct Cli {
     /// whether unicode symbols are used to improve the overall look of the app
     #[argh(option, default = "true")]
     enhanced_graphics: bool,
+    /// offline ip-to-ASN table (iptoasn TSV or `prefix/len<TAB>asn` lines), used instead of DNS lookups
+    #[argh(option)]
+    asn_db: Option<String>,
+    /// resolver for Team Cymru origin AS lookups (e.g. 1.1.1.1), off unless given as each hop's address is sent to it
+    #[argh(option)]
+    asn_resolver: Option<String>,
+    /// a PeeringDB JSON export used to identify and place IXP crossings
+    #[argh(option)]
+    peeringdb: Option<String>,
+    /// directory searched for map data ahead of the working and XDG data directories, repeatable
+    #[argh(option)]
+    map_data: Vec<String>,
+    /// coastline data, as `lon lat` text, GeoJSON or a shapefile (default: world.txt if found, else a bundled low resolution world)
+    #[argh(option)]
+    coastlines: Option<String>,
+    /// country border data, shown with TAB, in any of the coastline formats (default: world_countries.txt if found, else the bundled coastlines)
+    #[argh(option)]
+    countries: Option<String>,
+    /// extra map layers drawn over the base map, e.g. provinces or lakes
+    #[argh(option)]
+    layer: Vec<String>,
+    /// hop marker colours by RTT in ms, as `ms:colour` stops (colours by name, #rrggbb or palette index)
+    #[argh(option, default = "String::from(\"0:green,50:yellow,150:red\")")]
+    rtt_colours: String,
+    /// link colours by the RTT in ms added across the link, as `ms:colour` stops
+    #[argh(option, default = "String::from(\"0:green,20:yellow,80:red\")")]
+    rtt_increase_colours: String,
+    /// degrees between the graticule lines shown with F7, at least 1
+    #[argh(option, default = "15.0")]
+    graticule: f32,
 }
 
-pub type DATA_TYPE = Rc<Vec<(f32, f32)>>;
+pub type DATA_TYPE = Rc<MapLayer>;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // const WIDTH: u32 = 15_000;
-    // const HEIGHT: u32 = 7_500;
-    /*const RADIUS: u32 = 1_000;
-
-    for i in 0..4 {
-        let offset = 90.0 * i as f32;
-        let mut image_buffer = ImageBuffer::<Rgb<u8>, _>::new(RADIUS * 2, RADIUS * 2);
-
-        let file = File::open("world_countries.txt")?;
-        let reader = BufReader::new(file);
-
-        fn draw(start: Option<(u32, u32)>, end: Option<(u32, u32)>, img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
-            if start.is_none() || end.is_none() || true { return; }
-            let ((x0, y0), (x1, y1)) = (start.unwrap(), end.unwrap());
-
-            let (mut x0, mut y0) = (x0 as i32, y0 as i32);
-            let (mut x1, mut y1) = (x1 as i32, y1 as i32);
-
-            let dx = (x1 - x0).abs();
-            let dy = (y1 - y0).abs();
-            let sx = if x0 < x1 { 1 } else { -1 };
-            let sy = if y0 < y1 { 1 } else { -1 };
-            let mut err = dx - dy;
-
-            loop {
-                *img.get_pixel_mut(x0 as u32, y0 as u32) = Rgb::from([0, 255, 0]);
-
-                if (x0 == x1) && (y0 == y1) { break; };
-                let e2 = 2 * err;
-                if e2 > -dy { err -= dy; x0 += sx; }
-                if e2 < dx { err += dx; y0 += sy; }
-            }
-        }
-
-        let mut shape_start = None;
-        let mut last_point = None;
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let line = line.trim();
-
-            if line.is_empty() {
-                draw(last_point, shape_start, &mut image_buffer);
-                shape_start = None;
-                last_point = None;
-                continue;
-            }
-
-            let numbers = line.split(' ').collect_vec();
-            if numbers.len() < 2 { continue };
-
-            let Ok(lat) = numbers[0].parse::<f32>() else { continue };
-            let Ok(long) = numbers[1].parse::<f32>() else { continue };
-
-            let lat = (((lat + offset) + 180.0) % 360.0) - 180.0;
-
-            if lat.abs() > 90.0 { continue };
-
-            // let x = (((x + 180.0) * ((WIDTH - 1) as f32)) / 360.0) as u32;
-            // let y = ((-(y - 90.0) * ((HEIGHT - 1) as f32)) / 180.0) as u32;
-
-            let lat = lat.to_radians();
-            let long = long.to_radians();
-
-            let _ = RADIUS as f32 + (RADIUS - 1) as f32 * lat.cos() * long.cos();
-            let y = RADIUS as f32 - (RADIUS - 1) as f32 * lat.cos() * long.sin();
-            let x = RADIUS as f32 + (RADIUS - 1) as f32 * lat.sin();
-            let (x, y) = (x as u32, y as u32);
-
-            draw(last_point, Some((x, y)), &mut image_buffer);
-
-            if shape_start.is_none() {
-                shape_start = Some((x, y));
-            }
-            last_point = Some((x, y));
-
-            *image_buffer.get_pixel_mut(x, y) = Rgb::from([255, 255, 255]);
-        }
-
-        draw(shape_start, last_point, &mut image_buffer);
-
-        image_buffer.save(format!("output{i}.png")).unwrap();
+    let cli: Cli = argh::from_env();
+    // Finer than a degree would be tens of thousands of lines to draw every frame
+    if cli.graticule.is_nan() || cli.graticule < 1.0 {
+        return Err("graticule spacing must be at least 1 degree".into());
     }
-
-    return Ok(());*/
+    let settings = Settings {
+        tick_rate: Duration::from_millis(cli.tick_rate),
+        enhanced_graphics: cli.enhanced_graphics,
+        graticule: graticule(cli.graticule),
+        rtt_colours: Gradient::parse(&cli.rtt_colours)?,
+        rtt_increase_colours: Gradient::parse(&cli.rtt_increase_colours)?,
+    };
 
     println!("Preloading...");
-    let mut data_countries = Vec::with_capacity(415_000);
-    let countries_file = File::open("world_countries.txt")?;
-
-    let mut data_world = Vec::with_capacity(455_000);
-    let world_file = File::open("world.txt")?;
-
-    let mut min = 10.1f32;
-    let mut max = 0.0f32;
-
-    for (file, data) in [(countries_file, &mut data_countries), (world_file, &mut data_world)] {
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let line = line.trim();
-
-            if line.is_empty() {
-                continue;
-            }
-
-            let numbers = line.split(' ').collect_vec();
-            if numbers.len() < 2 { continue };
-            let Ok(lat) = numbers[0].parse::<f32>() else { continue };
-            let Ok(long) = numbers[1].parse::<f32>() else { continue };
-
-            let lat = (lat + 180.0) / 360.0;
-            let long = -(long - 90.0) / 180.0;
-
-
-            data.push((lat, long));
-        }
+    let search = data_search_path(&cli.map_data);
+    let data_countries = Rc::new(MapLayer::new(load_map(cli.countries.as_deref(), "world_countries.txt", &search)?));
+    let data_world = Rc::new(MapLayer::new(load_map(cli.coastlines.as_deref(), "world.txt", &search)?));
+    let layers = cli.layer.iter()
+        .map(|path| Ok(Rc::new(MapLayer::new(load_map(Some(path), "", &search)?))))
+        .collect::<Result<Vec<DATA_TYPE>, Box<dyn Error>>>()?;
+
+    let asn_lookup = if let Some(path) = &cli.asn_db {
+        AsnLookup::Table(Arc::new(AsnTable::load(path)?))
     }
+    else if let Some(resolver) = cli.asn_resolver.as_ref().filter(|r| !r.is_empty()) {
+        AsnLookup::Dns(resolver.clone())
+    }
+    else {
+        AsnLookup::Disabled
+    };
 
-    let data_countries = Rc::new(data_countries);
-    let data_world = Rc::new(data_world);
-
-    // println!("{:?}", conv_coords(51.500000, -0.125000, 1.0, (0.5, 0.5)));
-    // println!("{:?}", conv_coords(160.500000, -0.125000, 1.0, (0.5, 0.5)));
-    // return Ok(());
+    let ixp_db = match &cli.peeringdb {
+        Some(path) => Some(Arc::new(IxpDb::load(path)?)),
+        None => None,
+    };
 
-    let cli: Cli = argh::from_env();
-    let tick_rate = Duration::from_millis(cli.tick_rate);
-    run(tick_rate, true, data_countries, data_world)?;
+    run(settings, data_countries, data_world, layers, asn_lookup, ixp_db)?;
     Ok(())
 }
-
-pub fn conv_coords(long: f32, lat: f32, zoom: f32, pos: (f32, f32)) -> (f32, f32) {
-    let long = (long + 180.0) / 360.0;
-    let lat = -(lat - 90.0) / 180.0;
-    let x = (long - pos.0) * zoom + 0.5;
-    let y = (lat - pos.1) * zoom + 0.5;
-    (x, 1.0 - y)
-}
\ No newline at end of file
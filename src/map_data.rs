@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use crate::loader::Shapes;
+use crate::projection::View;
+
+const CELL_SIZE: f32 = 10.0;
+const COLUMNS: usize = 36;
+const ROWS: usize = 18;
+
+/// Each level keeps a quarter of the vertices of the one before it
+const LEVELS: usize = 3;
+
+struct Lod {
+    points: Vec<(f32, f32)>,
+    /// Runs of connected vertices whose segments start in each grid cell, row-major from the north-west
+    cells: Vec<Vec<Range<usize>>>,
+    /// Every shape whole, for filling and point-in-polygon tests
+    shapes: Vec<Range<usize>>,
+}
+
+impl Lod {
+    fn new(shapes: &[Vec<(f32, f32)>], step: usize) -> Lod {
+        let mut points = Vec::new();
+        let mut cells = vec![Vec::new(); COLUMNS * ROWS];
+        let mut ranges = Vec::with_capacity(shapes.len());
+
+        for shape in shapes {
+            let start = points.len();
+            points.extend(shape.iter().step_by(step));
+            // Keep the last vertex so outlines still close
+            if (shape.len() - 1) % step != 0 {
+                points.push(*shape.last().unwrap());
+            }
+
+            ranges.push(start..points.len());
+
+            if points.len() - start == 1 {
+                cells[cell_index(points[start])].push(start..start + 1);
+                continue;
+            }
+
+            // Each run ends on the first vertex of the next so the line stays connected across cells
+            let mut run = (cell_index(points[start]), start);
+            for (i, point) in points.iter().enumerate().take(points.len() - 1).skip(start + 1) {
+                let cell = cell_index(*point);
+                if cell != run.0 {
+                    cells[run.0].push(run.1..i + 1);
+                    run = (cell, i);
+                }
+            }
+            cells[run.0].push(run.1..points.len());
+        }
+
+        Lod { points, cells, shapes: ranges }
+    }
+}
+
+fn cell_index((long, lat): (f32, f32)) -> usize {
+    let column = (((long + 180.0) / CELL_SIZE) as usize).min(COLUMNS - 1);
+    let row = (((90.0 - lat) / CELL_SIZE) as usize).min(ROWS - 1);
+    row * COLUMNS + column
+}
+
+/// Polylines bucketed into a lat/long grid, with decimated copies for low zoom levels
+pub struct MapLayer {
+    lods: Vec<Lod>,
+    /// Feature of each shape, whether it's a closed outline, and its bounding box
+    features: Vec<usize>,
+    rings: Vec<bool>,
+    bounds: Vec<((f32, f32), (f32, f32))>,
+    pub names: Vec<String>,
+    pub codes: Vec<String>,
+}
+
+impl MapLayer {
+    pub fn new(shapes: Shapes) -> MapLayer {
+        let (mut lines, mut features, mut rings) = (Vec::new(), Vec::new(), Vec::new());
+        for ((mut shape, feature), ring) in shapes.lines.into_iter().zip(shapes.features).zip(shapes.rings) {
+            if shape.is_empty() { continue };
+            if ring && shape.first() != shape.last() {
+                shape.push(shape[0]);
+            }
+            lines.push(shape);
+            features.push(feature);
+            rings.push(ring);
+        }
+
+        let bounds = lines.iter()
+            .map(|shape| shape.iter().fold(
+                ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN)),
+                |(min, max), p| ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1))),
+            ))
+            .collect();
+
+        let lods = (0..LEVELS)
+            .map(|level| Lod::new(&lines, 1 << (2 * level)))
+            .collect();
+        MapLayer { lods, features, rings, bounds, names: shapes.names, codes: shapes.codes }
+    }
+
+    /// Each doubling of zoom quarters the visible area, so the matching level keeps a quarter of the
+    /// vertices and the number drawn stays about constant
+    fn lod(&self, view: &View) -> &Lod {
+        let level = ((4.0 / view.zoom).log2().floor().max(0.0) as usize).min(LEVELS - 1);
+        &self.lods[level]
+    }
+
+    /// The feature whose outlines enclose the point, by the even-odd rule so holes are left out.
+    /// Open lines enclose nothing.
+    pub fn feature_at(&self, long: f32, lat: f32) -> Option<usize> {
+        let lod = &self.lods[0];
+        let mut inside = HashSet::new();
+
+        for (i, ((min, max), range)) in self.bounds.iter().zip(&lod.shapes).enumerate() {
+            if !self.rings[i] || long < min.0 || long > max.0 || lat < min.1 || lat > max.1 { continue };
+
+            let crossings = lod.points[range.clone()].windows(2)
+                .filter(|edge| {
+                    let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+                    (y1 > lat) != (y2 > lat) && long < x1 + (lat - y1) / (y2 - y1) * (x2 - x1)
+                })
+                .count();
+            if crossings % 2 == 1 && !inside.remove(&self.features[i]) {
+                inside.insert(self.features[i]);
+            }
+        }
+
+        inside.into_iter().min()
+    }
+
+    /// Whole rings at full detail with their feature, at least partly visible on one copy of the world.
+    /// Decimating would collapse small polygons, which matters more for fills than outlines.
+    pub fn shapes<'a>(&'a self, view: &'a View, copy: i32) -> impl Iterator<Item = (usize, &'a [(f32, f32)])> + 'a {
+        let lod = &self.lods[0];
+
+        lod.shapes.iter().zip(&self.bounds).zip(&self.features).zip(&self.rings)
+            .filter(move |(((_, (min, max)), _), ring)| **ring && view.sees(copy, *min, *max))
+            .map(move |(((range, _), feature), _)| (*feature, &lod.points[range.clone()]))
+    }
+
+    /// Connected runs of vertices in the cells visible on one copy of the world, at a detail suiting the zoom
+    pub fn visible<'a>(&'a self, view: &'a View, copy: i32) -> impl Iterator<Item = &'a [(f32, f32)]> + 'a {
+        let lod = self.lod(view);
+
+        lod.cells.iter().enumerate()
+            .filter(move |(i, _)| {
+                let long = (i % COLUMNS) as f32 * CELL_SIZE - 180.0;
+                let lat = 90.0 - (i / COLUMNS) as f32 * CELL_SIZE;
+                view.sees(copy, (long, lat - CELL_SIZE), (long + CELL_SIZE, lat))
+            })
+            .flat_map(move |(_, runs)| runs.iter().map(|run| &lod.points[run.clone()]))
+    }
+}
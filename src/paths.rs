@@ -0,0 +1,44 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from)
+}
+
+/// `$XDG_CACHE_HOME/trace`, falling back to `~/.cache/trace`
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(home()?.join(".cache")))
+        .map(|d| d.join("trace"))
+}
+
+/// Where map data is looked for: the `--map-data` directories, the working directory,
+/// then `trace` under `$XDG_DATA_HOME` and each of `$XDG_DATA_DIRS`
+pub fn data_search_path(map_data: &[String]) -> Vec<PathBuf> {
+    let mut dirs = map_data.iter().map(PathBuf::from).collect::<Vec<_>>();
+    dirs.push(PathBuf::from("."));
+
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(home()?.join(".local/share")));
+    dirs.extend(data_home.map(|d| d.join("trace")));
+
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(data_dirs.split(':').filter(|d| !d.is_empty()).map(|d| Path::new(d).join("trace")));
+
+    dirs
+}
+
+pub fn find_data(name: &str, search: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.exists().then(|| path.to_path_buf());
+    }
+    search.iter().map(|dir| dir.join(path)).find(|p| p.exists())
+}
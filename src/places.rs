@@ -0,0 +1,29 @@
+use std::sync::OnceLock;
+use reverse_geocoder::ReverseGeocoder;
+use crate::geo::EARTH_RADIUS_KM;
+
+/// Hops further than this from any city aren't named after one
+const MAX_CITY_DISTANCE_KM: f64 = 100.0;
+
+/// GeoNames cities embedded in the binary, indexed on first use
+static GEOCODER: OnceLock<ReverseGeocoder> = OnceLock::new();
+
+pub struct City {
+    pub name: String,
+    /// ISO 3166-1 alpha-2 country code
+    pub cc: String,
+}
+
+pub fn nearest_city(lat: f32, long: f32) -> Option<City> {
+    if lat.is_nan() || long.is_nan() {
+        return None;
+    }
+
+    let result = GEOCODER.get_or_init(ReverseGeocoder::new).search((lat as f64, long as f64));
+    // The distance is the squared chord between points on the unit sphere
+    let distance = result.distance.sqrt() * EARTH_RADIUS_KM as f64;
+    (distance <= MAX_CITY_DISTANCE_KM).then(|| City {
+        name: result.record.name.clone(),
+        cc: result.record.cc.clone(),
+    })
+}
@@ -0,0 +1,283 @@
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use ratatui::layout::Rect;
+use crate::app::App;
+
+/// Maps (long, lat) in degrees onto the map's normalised 0..1 space, x rightwards and y downwards
+pub trait Projection {
+    fn project(&self, long: f32, lat: f32) -> Option<(f32, f32)>;
+
+    /// The (long, lat) projected to a normalised map point, `None` off the world
+    fn unproject(&self, x: f32, y: f32) -> Option<(f32, f32)>;
+
+    /// Edge of the projected world, drawn beneath the map data
+    fn outline(&self) -> Vec<(f32, f32)> {
+        Vec::new()
+    }
+
+    /// Whether any of a lat/long box can be seen, projecting its corners when `None`
+    fn box_visible(&self, _min: (f32, f32), _max: (f32, f32)) -> Option<bool> {
+        None
+    }
+}
+
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn project(&self, long: f32, lat: f32) -> Option<(f32, f32)> {
+        Some(((long + 180.0) / 360.0, (90.0 - lat) / 180.0))
+    }
+
+    fn unproject(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        Some((x * 360.0 - 180.0, 90.0 - y * 180.0))
+    }
+}
+
+pub struct Mercator;
+
+const MERCATOR_MAX_LAT: f32 = 85.051_13;
+
+impl Projection for Mercator {
+    fn project(&self, long: f32, lat: f32) -> Option<(f32, f32)> {
+        let lat = lat.clamp(-MERCATOR_MAX_LAT, MERCATOR_MAX_LAT).to_radians();
+        let y = (PI / 4.0 + lat / 2.0).tan().ln();
+        Some(((long + 180.0) / 360.0, 0.5 - y / (2.0 * PI)))
+    }
+
+    fn unproject(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let lat = ((0.5 - y) * 2.0 * PI).sinh().atan().to_degrees();
+        Some((x * 360.0 - 180.0, lat))
+    }
+}
+
+pub struct Robinson;
+
+// Robinson's table of parallel lengths and distances from the equator, every 5 degrees
+const ROBINSON_X: [f32; 19] = [
+    1.0000, 0.9986, 0.9954, 0.9900, 0.9822, 0.9730, 0.9600, 0.9427, 0.9216, 0.8962,
+    0.8679, 0.8350, 0.7986, 0.7597, 0.7186, 0.6732, 0.6213, 0.5722, 0.5322,
+];
+const ROBINSON_Y: [f32; 19] = [
+    0.0000, 0.0620, 0.1240, 0.1860, 0.2480, 0.3100, 0.3720, 0.4340, 0.4958, 0.5571,
+    0.6176, 0.6769, 0.7346, 0.7903, 0.8435, 0.8936, 0.9394, 0.9761, 1.0000,
+];
+
+impl Projection for Robinson {
+    fn project(&self, long: f32, lat: f32) -> Option<(f32, f32)> {
+        let i = (lat.abs().min(90.0) / 5.0).min(17.999);
+        let t = i.fract();
+        let i = i as usize;
+        let x = ROBINSON_X[i] + (ROBINSON_X[i + 1] - ROBINSON_X[i]) * t;
+        let y = ROBINSON_Y[i] + (ROBINSON_Y[i + 1] - ROBINSON_Y[i]) * t;
+        Some((0.5 + x * long / 360.0, 0.5 - y.copysign(lat) / 2.0))
+    }
+
+    fn unproject(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let distance = ((0.5 - y) * 2.0).abs();
+        if distance > 1.0 {
+            return None;
+        }
+        let i = ROBINSON_Y.partition_point(|d| *d <= distance).clamp(1, 18) - 1;
+        let t = (distance - ROBINSON_Y[i]) / (ROBINSON_Y[i + 1] - ROBINSON_Y[i]);
+        let lat = ((i as f32 + t) * 5.0).copysign(0.5 - y);
+        let long = (x - 0.5) * 360.0 / (ROBINSON_X[i] + (ROBINSON_X[i + 1] - ROBINSON_X[i]) * t);
+        (long.abs() <= 180.0).then_some((long, lat))
+    }
+
+    fn outline(&self) -> Vec<(f32, f32)> {
+        (-90..=90).flat_map(|lat| [
+            self.project(-180.0, lat as f32).unwrap(),
+            self.project(180.0, lat as f32).unwrap(),
+        ]).collect()
+    }
+}
+
+/// Globe seen from above `centre` (long, lat), points on the far side are hidden
+pub struct Orthographic {
+    pub centre: (f32, f32),
+}
+
+impl Projection for Orthographic {
+    fn project(&self, long: f32, lat: f32) -> Option<(f32, f32)> {
+        let (long, lat) = (long.to_radians(), lat.to_radians());
+        let (long0, lat0) = (self.centre.0.to_radians(), self.centre.1.to_radians());
+
+        let cos_c = lat0.sin() * lat.sin() + lat0.cos() * lat.cos() * (long - long0).cos();
+        if cos_c < 0.0 {
+            return None;
+        }
+
+        let x = lat.cos() * (long - long0).sin();
+        let y = lat0.cos() * lat.sin() - lat0.sin() * lat.cos() * (long - long0).cos();
+        Some((0.5 + x / 2.0, 0.5 - y / 2.0))
+    }
+
+    fn unproject(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (x, y) = ((x - 0.5) * 2.0, (0.5 - y) * 2.0);
+        let rho = x.hypot(y);
+        if rho > 1.0 {
+            return None;
+        }
+        if rho < 1e-6 {
+            return Some(self.centre);
+        }
+
+        let c = rho.asin();
+        let (long0, lat0) = (self.centre.0.to_radians(), self.centre.1.to_radians());
+        let lat = (c.cos() * lat0.sin() + y * c.sin() * lat0.cos() / rho).asin();
+        let long = long0 + (x * c.sin()).atan2(rho * c.cos() * lat0.cos() - y * c.sin() * lat0.sin());
+        Some(((long.to_degrees() + 540.0).rem_euclid(360.0) - 180.0, lat.to_degrees()))
+    }
+
+    fn box_visible(&self, min: (f32, f32), max: (f32, f32)) -> Option<bool> {
+        // Curved edges make the corners a poor bound, only reject boxes wholly on the far side
+        let centre = ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0);
+        let radius = (max.0 - min.0).hypot(max.1 - min.1) / 2.0;
+        Some(angular_distance(centre, self.centre) <= 90.0 + radius)
+    }
+
+    fn outline(&self) -> Vec<(f32, f32)> {
+        (0..360).map(|i| {
+            let a = (i as f32).to_radians();
+            (0.5 + a.cos() / 2.0, 0.5 + a.sin() / 2.0)
+        }).collect()
+    }
+}
+
+pub fn angular_distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (a, b) = ((a.0.to_radians(), a.1.to_radians()), (b.0.to_radians(), b.1.to_radians()));
+    (a.1.sin() * b.1.sin() + a.1.cos() * b.1.cos() * (a.0 - b.0).cos()).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProjectionKind {
+    Equirectangular,
+    Mercator,
+    Robinson,
+    Orthographic,
+}
+
+impl ProjectionKind {
+    pub fn next(self) -> Self {
+        match self {
+            ProjectionKind::Equirectangular => ProjectionKind::Mercator,
+            ProjectionKind::Mercator => ProjectionKind::Robinson,
+            ProjectionKind::Robinson => ProjectionKind::Orthographic,
+            ProjectionKind::Orthographic => ProjectionKind::Equirectangular,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectionKind::Equirectangular => "Equirectangular",
+            ProjectionKind::Mercator => "Mercator",
+            ProjectionKind::Robinson => "Robinson",
+            ProjectionKind::Orthographic => "Orthographic",
+        }
+    }
+}
+
+/// Projection plus the zoom and pan of the map canvas, shared by every map layer
+pub struct View {
+    pub projection: Box<dyn Projection>,
+    pub zoom: f32,
+    pub pos: (f32, f32),
+    pub scale: (f32, f32),
+    /// Whether the world repeats horizontally
+    pub wrap: bool,
+}
+
+impl View {
+    pub fn new(app: &App, area: Rect) -> View {
+        match app.projection {
+            ProjectionKind::Orthographic => {
+                // Terminal cells are roughly twice as tall as they are wide, keep the globe round
+                let aspect = area.width.saturating_sub(2).max(1) as f32 / (2 * area.height.saturating_sub(2).max(1)) as f32;
+                View {
+                    projection: Box::new(Orthographic { centre: app.globe_rot }),
+                    zoom: app.zoom,
+                    pos: (0.5, 0.5),
+                    scale: if aspect > 1.0 { (1.0 / aspect, 1.0) } else { (1.0, aspect) },
+                    wrap: false,
+                }
+            }
+            kind => View {
+                projection: match kind {
+                    ProjectionKind::Mercator => Box::new(Mercator),
+                    ProjectionKind::Robinson => Box::new(Robinson),
+                    _ => Box::new(Equirectangular),
+                },
+                zoom: app.zoom,
+                pos: app.map_pos,
+                scale: (1.0, 1.0),
+                wrap: true,
+            },
+        }
+    }
+
+    /// Horizontal copies of the world overlapping the canvas
+    pub fn copies(&self) -> RangeInclusive<i32> {
+        if !self.wrap {
+            return 0..=0;
+        }
+        let half_width = 0.5 / (self.zoom * self.scale.0);
+        (self.pos.0 - half_width).floor() as i32..=(self.pos.0 + half_width).ceil() as i32 - 1
+    }
+
+    /// Canvas position of a normalised map point on the given copy of the world
+    pub fn place(&self, copy: i32, (x, y): (f32, f32)) -> (f32, f32) {
+        let x = (x + copy as f32 - self.pos.0) * self.zoom * self.scale.0 + 0.5;
+        let y = (y - self.pos.1) * self.zoom * self.scale.1 + 0.5;
+        (x, 1.0 - y)
+    }
+
+    /// Whether any of a lat/long box on the given copy of the world lands on the canvas
+    pub fn sees(&self, copy: i32, min: (f32, f32), max: (f32, f32)) -> bool {
+        if let Some(visible) = self.projection.box_visible(min, max) {
+            return visible;
+        }
+
+        let corners = [(min.0, min.1), (min.0, max.1), (max.0, min.1), (max.0, max.1)]
+            .map(|(long, lat)| self.to_canvas(copy, long, lat));
+        let Some(corners) = corners.into_iter().collect::<Option<Vec<_>>>() else { return true };
+
+        let (x0, x1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), (x, _)| (lo.min(*x), hi.max(*x)));
+        let (y0, y1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), (_, y)| (lo.min(*y), hi.max(*y)));
+        x0 <= 1.0 && x1 >= 0.0 && y0 <= 1.0 && y1 >= 0.0
+    }
+
+    /// Lat/long at the middle of the canvas as (long, lat), `None` when it's off the world
+    pub fn centre(&self) -> Option<(f32, f32)> {
+        self.projection.unproject(self.pos.0.rem_euclid(1.0), self.pos.1)
+    }
+
+    pub fn to_canvas(&self, copy: i32, long: f32, lat: f32) -> Option<(f32, f32)> {
+        if long.is_nan() || lat.is_nan() {
+            return None;
+        }
+        Some(self.place(copy, self.projection.project(long, lat)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unproject_inverts_project() {
+        let projections: [Box<dyn Projection>; 4] = [
+            Box::new(Equirectangular),
+            Box::new(Mercator),
+            Box::new(Robinson),
+            Box::new(Orthographic { centre: (10.0, 40.0) }),
+        ];
+        for projection in &projections {
+            for (long, lat) in [(0.0, 0.0), (-0.12, 51.5), (139.7, 35.6), (-74.0, -33.0), (20.0, 70.0)] {
+                // The globe's far side doesn't project
+                let Some((x, y)) = projection.project(long, lat) else { continue; };
+                let (long2, lat2) = projection.unproject(x, y).unwrap();
+                assert!((long - long2).abs() < 0.01 && (lat - lat2).abs() < 0.01, "({long}, {lat}) came back as ({long2}, {lat2})");
+            }
+        }
+    }
+}
@@ -1,19 +1,23 @@
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     symbols,
     terminal::Frame,
     text::{self, Span},
     widgets::{
         canvas::{self, Canvas, Circle, Map, MapResolution, Rectangle},
-        Axis, BarChart, Block, Cell, Chart, Dataset, Gauge, LineGauge, List, ListItem, Paragraph,
+        Axis, BarChart, Block, Cell, Chart, Clear, Dataset, Gauge, LineGauge, List, ListItem, Paragraph,
         Row, Sparkline, Table, Tabs, Wrap,
     },
 };
+use itertools::Itertools;
 use ratatui::style::Stylize;
-use crate::app::App;
-use crate::conv_coords;
-use crate::custom_map::CMap;
+use std::collections::{HashMap, HashSet};
+use crate::app::{describe_annotation, App, AsRow, CountryFill};
+use crate::custom_map::{CMap, FeatureFill, Polylines};
+use crate::geo::{great_circle, meridian, parallel, split_antimeridian, TROPIC_LAT};
+use crate::gradient::Gradient;
+use crate::projection::View;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).split(f.size());
@@ -31,6 +35,10 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         0 => draw_first_tab(f, app, chunks[1]),
         _ => {}
     };
+
+    if app.show_hop_detail {
+        draw_hop_detail(f, app, f.size());
+    }
 }
 
 
@@ -76,7 +84,9 @@ fn draw_first_tab(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks =
         Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).split(area);
 
-    let h_chunks = Layout::vertical([Constraint::Length(3), Constraint::Fill(1), Constraint::Length(3)]).split(chunks[0]);
+    // The AS path colours by AS, so the RTT legend only goes with the servers table
+    let legend_height = if app.as_view { 0 } else { 4 };
+    let h_chunks = Layout::vertical([Constraint::Length(3), Constraint::Fill(1), Constraint::Length(legend_height), Constraint::Length(3)]).split(chunks[0]);
 
     let table = Table::new(
         [Row::new(vec![format!("> {}", app.input)]).style(Style::default().bold())],
@@ -87,25 +97,38 @@ fn draw_first_tab(f: &mut Frame, app: &mut App, area: Rect) {
     .block(Block::bordered().title("Input"));
     f.render_widget(table, h_chunks[0]);
 
-    let rows = app.trace_result.iter().enumerate().map(|(_, t)| {
-        Row::new(vec![t.no.clone(), t.ip.clone(), t.name.clone(), t.time.clone()]).style(Style::default())
-    });
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Length(5),
-            Constraint::Length(16),
-            Constraint::Fill(1),
-            Constraint::Length(10),
-        ],
-    )
-    .header(
-        Row::new(vec!["No.", "IP", "Name", "Time"])
-            .style(Style::default().fg(Color::Yellow))
-            .bottom_margin(1),
-    )
-    .block(Block::bordered().title("Servers"));
-    f.render_widget(table, h_chunks[1]);
+    if app.as_view {
+        draw_as_path(f, app, h_chunks[1]);
+    }
+    else {
+        let rows = app.trace_result.iter().enumerate().map(|(_, t)| {
+            let asn = match (&t.ixp, t.asn) {
+                (Some(ixp), _) => format!("IX {ixp}"),
+                (None, Some(asn)) => format!("{asn} {}", t.as_name),
+                (None, None) => "-".to_string(),
+            };
+            Row::new(vec![t.no.clone(), t.ip.clone(), t.name.clone(), asn, t.place(), t.time.clone()]).style(Style::default())
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(5),
+                Constraint::Length(16),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Length(10),
+            ],
+        )
+        .header(
+            Row::new(vec!["No.", "IP", "Name", "AS", "Place", "Time"])
+                .style(Style::default().fg(Color::Yellow))
+                .bottom_margin(1),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title("Servers - F2 for AS path, PGUP/PGDN to select, HOME to centre, ENTER for details"));
+        f.render_stateful_widget(table, h_chunks[1], &mut app.servers_state);
+    }
 
     let table = Table::new(
         [Row::new(vec![format!(" {}", app.status.clone())]).style(
@@ -121,44 +144,191 @@ fn draw_first_tab(f: &mut Frame, app: &mut App, area: Rect) {
         ],
     )
         .block(Block::bordered().title("Status"));
-    f.render_widget(table, h_chunks[2]);
+    f.render_widget(table, h_chunks[3]);
+
+    if !app.as_view {
+        draw_legend(f, app, h_chunks[2]);
+    }
+
+    let groups = app.as_groups();
+    let mut entry_group = vec![0; app.trace_result.len()];
+    for (g, group) in groups.iter().enumerate() {
+        entry_group[group.entries.clone()].fill(g);
+    }
+
+    app.map_area = chunks[1];
+    let selected = app.servers_state.selected().filter(|i| *i < app.trace_result.len());
+    let view = View::new(app, chunks[1]);
+    let map_size = (chunks[1].width.saturating_sub(2), chunks[1].height.saturating_sub(2));
+    let clusters = cluster_markers(app, &view, map_size);
+
+    // Countries along the route, shaded from green to red by the order they're entered or the latency reaching them
+    let traversed = app.traversed_countries();
+    let max_rtt = traversed.iter().filter_map(|c| c.rtt).fold(0.0f32, f32::max);
+    let fills = traversed.iter().enumerate()
+        .filter_map(|(n, c)| {
+            let fraction = match app.country_fill {
+                CountryFill::Off => return None,
+                CountryFill::HopOrder => n as f32 / (traversed.len() - 1).max(1) as f32,
+                CountryFill::Latency => c.rtt.map_or(0.0, |rtt| rtt / max_rtt.max(1.0)),
+            };
+            Some((c.country, fill_colour(fraction)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut block = Block::bordered().title(format!(
+        "World ({}) - TAB borders, F3 projection, F4 shading ({}), F5 follow route ({}), F6 animate ({}), F7 graticule, F8 equator",
+        app.projection.name(),
+        app.country_fill.name(),
+        if app.follow_route { "on" } else { "off" },
+        if app.pulse.is_some() { "on" } else { "off" },
+    ));
+    if app.country_fill != CountryFill::Off && !traversed.is_empty() {
+        let mut legend = vec![Span::raw(" ")];
+        for (n, c) in traversed.iter().enumerate() {
+            if n > 0 {
+                legend.push(Span::raw(" → "));
+            }
+            let name = &app.data_countries.names[c.country];
+            let name = if name.is_empty() { format!("#{}", c.country) } else { name.clone() };
+            legend.push(Span::styled(name, Style::default().fg(fills[&c.country])));
+        }
+        legend.push(Span::raw(" "));
+        block = block.title_bottom(text::Line::from(legend));
+    }
+    let centre = match view.centre() {
+        Some((long, lat)) => format!("{lat:.2}, {long:.2}"),
+        None => "-".to_string(),
+    };
+    block = block.title_bottom(text::Line::from(format!(" Centre {centre}, zoom {:.1}x ", app.zoom)).right_aligned());
 
     let map = Canvas::default()
-        .block(Block::bordered().title("World - TAB to enable borders"))
+        .block(block)
         .paint(|ctx| {
+            if !fills.is_empty() {
+                ctx.draw(&FeatureFill {
+                    data: app.data_countries.clone(),
+                    view: &view,
+                    colours: &fills,
+                });
+                ctx.layer();
+            }
+            // Beneath the coastlines so they stay readable where the lines cross them
+            if app.show_graticule || app.show_reference_lines {
+                if app.show_graticule {
+                    ctx.draw(&Polylines {
+                        lines: &app.settings.graticule,
+                        view: &view,
+                        color: Color::DarkGray,
+                    });
+                }
+                if app.show_reference_lines {
+                    ctx.draw(&Polylines {
+                        lines: &[parallel(TROPIC_LAT), parallel(-TROPIC_LAT)],
+                        view: &view,
+                        color: Color::Blue,
+                    });
+                    ctx.draw(&Polylines {
+                        lines: &[parallel(0.0), meridian(180.0)],
+                        view: &view,
+                        color: Color::Cyan,
+                    });
+                }
+                ctx.layer();
+            }
             ctx.draw(&CMap {
                 data: if app.show_countries { app.data_countries.clone() } else { app.data_world.clone() },
-                pos: app.map_pos,
-                zoom: app.zoom
+                view: &view,
+                color: Color::White,
             });
+            for layer in &app.layers {
+                ctx.draw(&CMap {
+                    data: layer.clone(),
+                    view: &view,
+                    color: Color::Gray,
+                });
+            }
             ctx.layer();
+            let mut selected_links = Vec::new();
             for (i, s1) in app.trace_result.iter().enumerate().filter(|(_, x)| !x.lat.is_nan()) {
-                let Some(s2) = app.trace_result.iter().skip(i + 1).filter(|x| !x.lat.is_nan()).next() else { break; };
+                let Some((j, s2)) = app.trace_result.iter().enumerate().skip(i + 1).find(|(_, x)| !x.lat.is_nan()) else { break; };
 
-                let (x1, y1) = conv_coords(s1.long, s1.lat, app.zoom, app.map_pos);
-                let (x2, y2) = conv_coords(s2.long, s2.lat, app.zoom, app.map_pos);
+                if selected == Some(i) || selected == Some(j) {
+                    selected_links.push(((s1.long, s1.lat), (s2.long, s2.lat)));
+                    continue;
+                }
+                let colour = if !app.as_view {
+                    match (s1.rtt(), s2.rtt()) {
+                        (Some(a), Some(b)) => app.settings.rtt_increase_colours.colour((b - a).max(0.0)),
+                        _ => Color::DarkGray,
+                    }
+                }
+                else if entry_group[i] == entry_group[j] {
+                    as_colour(entry_group[i])
+                }
+                else {
+                    Color::White
+                };
+                draw_link(ctx, &view, (s1.long, s1.lat), (s2.long, s2.lat), colour);
+            }
+            // The selected hop's links go over the rest
+            if !selected_links.is_empty() {
+                ctx.layer();
+                for (from, to) in selected_links {
+                    draw_link(ctx, &view, from, to, Color::LightRed);
+                }
+            }
 
-                let (x1, y1, x2, y2) = constrain(x1, y1, x2, y2);
+            let marker_style = |i: usize| {
+                let s = &app.trace_result[i];
+                let style = Style::default().fg(
+                    if app.as_view { as_colour(entry_group[i]) }
+                    else if s.ixp.is_some() { Color::LightMagenta }
+                    else { s.rtt().map_or(Color::DarkGray, |rtt| app.settings.rtt_colours.colour(rtt)) }
+                );
+                if selected == Some(i) { style.add_modifier(Modifier::BOLD | Modifier::REVERSED) } else { style }
+            };
+            // The cluster holding the selected hop is expanded to a line per hop below it
+            let (expanded, collapsed): (Vec<_>, Vec<_>) = clusters.iter()
+                .partition(|c| selected.is_some_and(|s| c.entries.contains(&s)));
+            let mut badges = collapsed.iter()
+                .map(|c| (c.x, c.y, c.cell, Span::styled(c.label.clone(), marker_style(*c.entries.last().unwrap())), c.entries[0]))
+                .collect_vec();
+            for cluster in expanded {
+                for (n, &i) in cluster.entries.iter().enumerate() {
+                    let y = cluster.y - n as f32 / (map_size.1.max(2) - 1) as f32;
+                    let cell = (cluster.cell.0, cluster.cell.1 + n as u16);
+                    badges.push((cluster.x, y, cell, Span::styled(app.trace_result[i].hop.to_string(), marker_style(i)), i));
+                }
+            }
 
-                ctx.draw(&canvas::Line {
-                    x1: x1 as f64,
-                    y1: y1 as f64,
-                    x2: x2 as f64,
-                    y2: y2 as f64,
-                    color: Color::Yellow,
-                });
+            // Hops sharing a place share its label, cut short before the next badge along the row
+            let mut labelled = HashSet::new();
+            for (x, y, cell, badge, i) in &badges {
+                let mut line = vec![badge.clone()];
+                let place = app.trace_result[*i].place();
+                let room = badges.iter()
+                    .filter(|b| b.2.1 == cell.1 && b.2.0 > cell.0)
+                    .map(|b| (b.2.0 - cell.0) as usize)
+                    .min()
+                    .unwrap_or(usize::MAX)
+                    .saturating_sub(badge.content.len() + 1);
+                if place != "-" && room > 1 && labelled.insert(place.clone()) {
+                    let place = format!(" {place}").chars().take(room).collect::<String>();
+                    line.push(Span::styled(place, Style::default().fg(Color::Gray)));
+                }
+                ctx.print(*x as f64, *y as f64, text::Line::from(line));
             }
 
-            for s in &app.trace_result {
-                let (x1, y1) = conv_coords(s.long, s.lat, app.zoom, app.map_pos);
-                ctx.print(
-                    x1 as f64,
-                    y1 as f64,
-                    Span::styled("X", Style::default().green()),
-                );
+            if let Some((long, lat)) = app.pulse_position() {
+                let pulse = Span::styled(if app.settings.enhanced_graphics { "●" } else { "*" }, Style::default().fg(Color::White).bold());
+                for copy in view.copies() {
+                    let Some((x, y)) = view.to_canvas(copy, long, lat) else { continue; };
+                    ctx.print(x as f64, y as f64, pulse.clone());
+                }
             }
         })
-        .marker(if app.enhanced_graphics {
+        .marker(if app.settings.enhanced_graphics {
             symbols::Marker::Braille
         } else {
             symbols::Marker::Dot
@@ -166,75 +336,440 @@ fn draw_first_tab(f: &mut Frame, app: &mut App, area: Rect) {
         .x_bounds([0.0, 1.0])
         .y_bounds([0.0, 1.0]);
     f.render_widget(map, chunks[1]);
+
+    if app.zoom > 1.0 {
+        draw_overview(f, app, &view, chunks[1]);
+    }
 }
 
-fn constrain(mut x1: f32, mut y1: f32, mut x2: f32, mut y2: f32) -> (f32, f32, f32, f32) {
-    if x2 == x1 || y1 == y2 {
-        return (x1, y1, x2, y2)
+/// The whole world in the corner of the map, with the part the map shows outlined
+fn draw_overview(f: &mut Frame, app: &App, view: &View, map_area: Rect) {
+    let inner = map_area.inner(Margin::new(1, 1));
+    let width = (inner.width / 4).min(40);
+    // Stretched like the map itself, so the outline keeps the map's shape
+    let height = (width as u32 * inner.height as u32 / inner.width.max(1) as u32) as u16 + 2;
+    if width < 16 || height < 6 {
+        return;
     }
-    if x1 == x2 {
-        return (x1, y1.max(0.0).min(1.0), x2, y2.max(0.0).min(1.0));
+    let area = Rect::new(inner.right() - width, inner.bottom() - height, width, height);
+
+    let mut overview = View::new(app, area);
+    overview.zoom = 1.0;
+    overview.pos = (0.5, 0.5);
+
+    // The map's canvas edges back in normalised map space
+    let half = (0.5 / (view.zoom * view.scale.0), 0.5 / (view.zoom * view.scale.1));
+    let (left, right) = (view.pos.0 - half.0, view.pos.0 + half.0);
+    let (top, bottom) = (view.pos.1 - half.1, view.pos.1 + half.1);
+    let edges = [
+        ((left, top), (right, top)),
+        ((right, top), (right, bottom)),
+        ((right, bottom), (left, bottom)),
+        ((left, bottom), (left, top)),
+    ];
+
+    let canvas = Canvas::default()
+        .block(Block::bordered().title("Overview"))
+        .paint(|ctx| {
+            ctx.draw(&CMap {
+                data: app.data_world.clone(),
+                view: &overview,
+                color: Color::Gray,
+            });
+            ctx.layer();
+            // Across the antimeridian the outline continues on the other side
+            let shifts = if view.wrap { -1..=1 } else { 0..=0 };
+            for shift in shifts {
+                for (from, to) in edges {
+                    let (x1, y1) = overview.place(shift, from);
+                    let (x2, y2) = overview.place(shift, to);
+                    let Some((x1, y1, x2, y2)) = clip_line(x1, y1, x2, y2) else { continue; };
+                    ctx.draw(&canvas::Line {
+                        x1: x1 as f64,
+                        y1: y1 as f64,
+                        x2: x2 as f64,
+                        y2: y2 as f64,
+                        color: Color::Yellow,
+                    });
+                }
+            }
+        })
+        .marker(if app.settings.enhanced_graphics {
+            symbols::Marker::Braille
+        } else {
+            symbols::Marker::Dot
+        })
+        .x_bounds([0.0, 1.0])
+        .y_bounds([0.0, 1.0]);
+    f.render_widget(Clear, area);
+    f.render_widget(canvas, area);
+}
+
+/// Draws the great-circle arc between two hops, broken wherever it leaves the projection
+fn draw_link(ctx: &mut canvas::Context, view: &View, from: (f32, f32), to: (f32, f32), color: Color) {
+    let parts = split_antimeridian(&great_circle(from, to, 2.0));
+    for copy in view.copies() {
+        for part in &parts {
+            let points = part.iter().map(|(long, lat)| view.to_canvas(copy, *long, *lat)).collect::<Vec<_>>();
+            for pair in points.windows(2) {
+                let (Some((x1, y1)), Some((x2, y2))) = (pair[0], pair[1]) else { continue; };
+                let Some((x1, y1, x2, y2)) = clip_line(x1, y1, x2, y2) else { continue; };
+
+                ctx.draw(&canvas::Line {
+                    x1: x1 as f64,
+                    y1: y1 as f64,
+                    x2: x2 as f64,
+                    y2: y2 as f64,
+                    color,
+                });
+            }
+        }
+    }
+}
+
+/// The RTT and RTT increase scales the map's markers and links are coloured on
+fn draw_legend(f: &mut Frame, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(2) as usize;
+    let scale = |name: &str, gradient: &Gradient| {
+        let range = format!(" {}-{} ms", gradient.min(), gradient.max());
+        let bar = width.saturating_sub(name.len() + range.len());
+        let mut spans = vec![Span::styled(name.to_string(), Style::default().fg(Color::Yellow))];
+        spans.extend((0..bar).map(|i| {
+            let value = gradient.min() + (gradient.max() - gradient.min()) * i as f32 / (bar - 1).max(1) as f32;
+            Span::styled("█", Style::default().fg(gradient.colour(value)))
+        }));
+        spans.push(Span::raw(range));
+        text::Line::from(spans)
+    };
+
+    let legend = Paragraph::new(vec![
+        scale("Hop RTT   ", &app.settings.rtt_colours),
+        scale("Link +RTT ", &app.settings.rtt_increase_colours),
+    ])
+    .block(Block::bordered().title("Legend"));
+    f.render_widget(legend, area);
+}
+
+/// Entry of the address that answered a probe. Extra probes ("-") were answered by the responder
+/// before them in the same hop.
+fn responder(app: &App, entry: usize) -> Option<usize> {
+    let hop = app.trace_result[entry].hop;
+    (0..=entry).rev()
+        .take_while(|i| app.trace_result[*i].hop == hop)
+        .find(|i| app.trace_result[*i].ip != "-")
+}
+
+/// Everything known about the selected hop, over the rest of the UI
+fn draw_hop_detail(f: &mut Frame, app: &App, area: Rect) {
+    let Some(selected) = app.servers_state.selected().filter(|i| *i < app.trace_result.len()) else { return; };
+    let t = &app.trace_result[responder(app, selected).unwrap_or(selected)];
+
+    let heading = Style::default().fg(Color::Yellow);
+    let field = |name: &str, value: String| text::Line::from(vec![
+        Span::styled(format!("{name:<13}"), heading),
+        Span::raw(value),
+    ]);
+
+    let as_owner = match t.asn {
+        Some(asn) => format!("AS{asn} {}", t.as_name),
+        None => "unknown".to_string(),
+    };
+    let location = if t.lat.is_nan() {
+        "unknown".to_string()
     }
-    if y1 == y2 {
-        return (x1.max(0.0).min(1.0), y1, x2.max(0.0).min(1.0), y2);
+    else {
+        format!("{} ({:.4}, {:.4})", t.place(), t.lat, t.long)
+    };
+    let (source, accuracy) = t.geo_source.as_ref()
+        .map_or(("none".to_string(), "-".to_string()), |g| g.describe());
+
+    let mut lines = vec![
+        field("Hop", t.hop.to_string()),
+        field("Address", t.ip.clone()),
+        field("Reverse DNS", if t.name == "-" { "none".to_string() } else { t.name.clone() }),
+        field("AS", as_owner),
+        field("IXP", t.ixp.clone().unwrap_or_else(|| "-".to_string())),
+        field("Location", location),
+        field("Geolocation", source),
+        field("Accuracy", accuracy),
+        text::Line::from(""),
+        text::Line::styled("Probes", heading),
+    ];
+
+    for (i, probe) in app.trace_result.iter().enumerate().filter(|(_, p)| p.hop == t.hop) {
+        let responder = responder(app, i)
+            .map(|r| &app.trace_result[r].ip)
+            .filter(|ip| *ip != "x")
+            .map_or("*", |ip| ip.as_str());
+        let annotations = probe.annotations.iter()
+            .map(|a| format!("{a} ({})", describe_annotation(a)))
+            .join(", ");
+        lines.push(text::Line::from(format!("  {:<5} {:<39} {:>10}  {annotations}", probe.no, responder, probe.time)));
     }
-    let gradient = (y2 - y1) / (x2 - x1);
-    let inv_grad = 1.0 / gradient;
 
-    if x1 < 0.0 || y1 < 0.0 {
-        let y_int = y1 - (gradient * x1);
-        let x_int = x1 - (inv_grad * y1);
-        if x_int > y_int {
-            x1 = x_int;
-            y1 = 0.0;
+    lines.push(text::Line::from(""));
+    lines.push(text::Line::styled("Raw", heading));
+    lines.push(text::Line::from(format!("  {}", t.raw)));
+
+    let popup = centered_rect(70, 60, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(Block::bordered().title(format!("Hop {} - ENTER or ESC to close", t.hop)))
+            .wrap(Wrap { trim: false }),
+        popup,
+    );
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::vertical([
+        Constraint::Percentage((100 - percent_y) / 2),
+        Constraint::Percentage(percent_y),
+        Constraint::Percentage((100 - percent_y) / 2),
+    ]).split(area);
+    Layout::horizontal([
+        Constraint::Percentage((100 - percent_x) / 2),
+        Constraint::Percentage(percent_x),
+        Constraint::Percentage((100 - percent_x) / 2),
+    ]).split(vertical[1])[1]
+}
+
+fn draw_as_path(f: &mut Frame, app: &mut App, area: Rect) {
+    let groups = app.as_groups();
+    let rows = app.as_rows(&groups).into_iter().map(|row| match row {
+        AsRow::Group(g) => {
+            let group = &groups[g];
+            let name = match group.asn {
+                Some(asn) => format!("AS{asn} {}", group.name),
+                None => "Unknown".to_string(),
+            };
+            let expanded = app.expanded_as.contains(&group.entries.start);
+            Row::new(vec![
+                format!("{} {name}", if expanded { "▾" } else { "▸" }),
+                format!("{}", group.hops),
+                format_rtt(group.entry_rtt),
+                format_rtt(group.exit_rtt),
+            ]).style(Style::default().fg(as_colour(g)))
         }
-        else {
-            y1 = y_int;
-            x1 = 0.0;
+        AsRow::Entry(e) => {
+            let t = &app.trace_result[e];
+            Row::new(vec![format!("    {} {}", t.no, t.ip), String::new(), t.time.clone(), String::new()])
         }
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(1),
+            Constraint::Length(4),
+            Constraint::Length(10),
+            Constraint::Length(10),
+        ],
+    )
+    .header(
+        Row::new(vec!["AS", "Hops", "Entry", "Exit"])
+            .style(Style::default().fg(Color::Yellow))
+            .bottom_margin(1),
+    )
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .block(Block::bordered().title("AS Path - F2 for servers, SPACE to expand"));
+    f.render_stateful_widget(table, area, &mut app.as_view_state);
+}
+
+/// Hop markers whose numbers would overlap on screen, drawn as one badge
+struct Cluster {
+    x: f32,
+    y: f32,
+    /// Terminal cell of the badge's first character
+    cell: (u16, u16),
+    entries: Vec<usize>,
+    label: String,
+}
+
+impl Cluster {
+    /// Whether the badges would run into each other, touching counts as the numbers would merge
+    fn overlaps(&self, other: &Cluster) -> bool {
+        self.cell.1 == other.cell.1
+            && self.cell.0 <= other.cell.0 + other.label.len() as u16
+            && other.cell.0 <= self.cell.0 + self.label.len() as u16
     }
+}
+
+/// Groups the visible hops by where their numbers land on the terminal, on each copy of the world
+fn cluster_markers(app: &App, view: &View, (width, height): (u16, u16)) -> Vec<Cluster> {
+    let mut clusters = Vec::new();
+    for copy in view.copies() {
+        let mut copy_clusters: Vec<Cluster> = Vec::new();
+        for (i, s) in app.trace_result.iter().enumerate() {
+            let Some((x, y)) = view.to_canvas(copy, s.long, s.lat) else { continue; };
+            if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) { continue; }
 
-    if x1 > 1.0 || y1 > 1.0 {
-        let y_int = y1 - (gradient * (x1 + 1.0));
-        let x_int = x1 - (inv_grad * (y1 + 1.0));
-        if x_int < y_int {
-            x1 = x_int;
-            y1 = 1.0;
+            let cell = (
+                (x * width.saturating_sub(1) as f32) as u16,
+                ((1.0 - y) * height.saturating_sub(1) as f32) as u16,
+            );
+            copy_clusters.push(Cluster { x, y, cell, entries: vec![i], label: s.hop.to_string() });
         }
-        else {
-            y1 = y_int;
-            x1 = 1.0;
+
+        // Merging widens the badge, which can make it reach another
+        while let Some((a, b)) = (0..copy_clusters.len()).tuple_combinations()
+            .find(|(a, b)| copy_clusters[*a].overlaps(&copy_clusters[*b]))
+        {
+            let merged = copy_clusters.remove(b);
+            let cluster = &mut copy_clusters[a];
+            if merged.cell.0 < cluster.cell.0 {
+                (cluster.x, cluster.y, cluster.cell) = (merged.x, merged.y, merged.cell);
+            }
+            cluster.entries.extend(merged.entries);
+            cluster.entries.sort();
+            let hops = cluster.entries.iter().map(|i| app.trace_result[*i].hop).collect_vec();
+            cluster.label = hop_ranges(&hops);
+        }
+        clusters.extend(copy_clusters);
+    }
+    clusters
+}
+
+/// Hop numbers as runs, e.g. `4-7` or `2,4-5`
+fn hop_ranges(hops: &[usize]) -> String {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &hop in hops.iter().sorted().dedup() {
+        match runs.last_mut() {
+            Some(run) if run.1 + 1 == hop => run.1 = hop,
+            _ => runs.push((hop, hop)),
         }
     }
+    runs.iter()
+        .map(|(first, last)| if first == last { first.to_string() } else { format!("{first}-{last}") })
+        .join(",")
+}
+
+/// Dimmed green through yellow to red, so outlines and the route stay readable over it
+fn fill_colour(fraction: f32) -> Color {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let red = (fraction * 2.0).min(1.0);
+    let green = ((1.0 - fraction) * 2.0).min(1.0);
+    Color::Rgb((red * 150.0) as u8, (green * 110.0) as u8, 0)
+}
 
-    let gradient = (y1 - y2) / (x1 - x2);
-    let inv_grad = 1.0 / gradient;
+fn format_rtt(rtt: Option<f32>) -> String {
+    rtt.map_or("-".to_string(), |rtt| format!("{rtt:.1} ms"))
+}
+
+const AS_COLOURS: [Color; 6] = [Color::Cyan, Color::Magenta, Color::LightGreen, Color::LightRed, Color::LightBlue, Color::Yellow];
+
+fn as_colour(group: usize) -> Color {
+    AS_COLOURS[group % AS_COLOURS.len()]
+}
+
+/// Liang–Barsky clipping of a line to the canvas' unit square, `None` when none of it is visible
+pub fn clip_line(x1: f32, y1: f32, x2: f32, y2: f32) -> Option<(f32, f32, f32, f32)> {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
 
-    if x2 < 0.0 || y2 < 0.0 {
-        let y_int = y2 - (gradient * x2);
-        let x_int = x2 - (inv_grad * y2);
-        if x_int > y_int {
-            x2 = x_int;
-            y2 = 0.0;
+    // Each edge as p * t <= q, p being the direction towards the outside of that edge
+    for (p, q) in [(-dx, x1), (dx, 1.0 - x1), (-dy, y1), (dy, 1.0 - y1)] {
+        if p == 0.0 {
+            // Parallel to this edge, either always inside or never
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+
+        let t = q / p;
+        if p < 0.0 {
+            t0 = t0.max(t);
         }
         else {
-            y2 = y_int;
-            x2 = 0.0;
+            t1 = t1.min(t);
+        }
+        if t0 > t1 {
+            return None;
         }
     }
 
-    if x2 > 1.0 || y2 > 1.0 {
-        let y_int = y2 - (gradient * (x2 + 1.0));
-        let x_int = x2 - (inv_grad * (y2 + 1.0));
-        if x_int < y_int {
-            x2 = x_int;
-            y2 = 1.0;
-        }
-        else {
-            y2 = y_int;
-            x2 = 1.0;
+    // Rounding can leave the ends a hair outside, which the painter would reject
+    Some((
+        (x1 + t0 * dx).clamp(0.0, 1.0),
+        (y1 + t0 * dy).clamp(0.0, 1.0),
+        (x1 + t1 * dx).clamp(0.0, 1.0),
+        (y1 + t1 * dy).clamp(0.0, 1.0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_line, hop_ranges};
+
+    fn assert_clip(line: (f32, f32, f32, f32), expected: Option<(f32, f32, f32, f32)>) {
+        let clipped = clip_line(line.0, line.1, line.2, line.3);
+        match (clipped, expected) {
+            (None, None) => {}
+            (Some(c), Some(e)) => {
+                let close = [(c.0, e.0), (c.1, e.1), (c.2, e.2), (c.3, e.3)].iter().all(|(a, b)| (a - b).abs() < 1e-5);
+                assert!(close, "{line:?} clipped to {c:?}, expected {e:?}");
+            }
+            _ => panic!("{line:?} clipped to {clipped:?}, expected {expected:?}"),
         }
     }
 
-    (x1, y1, x2, y2)
-}
\ No newline at end of file
+    #[test]
+    fn inside_is_unchanged() {
+        assert_clip((0.1, 0.2, 0.8, 0.9), Some((0.1, 0.2, 0.8, 0.9)));
+        assert_clip((0.0, 0.0, 1.0, 1.0), Some((0.0, 0.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn outside_is_rejected() {
+        assert_clip((-0.5, 0.2, -0.1, 0.8), None);
+        assert_clip((1.1, 0.2, 1.5, 0.8), None);
+        assert_clip((0.2, -0.5, 0.8, -0.1), None);
+        assert_clip((0.2, 1.1, 0.8, 1.5), None);
+        // Spans two edges' ranges but misses the corner
+        assert_clip((-0.5, 0.6, 0.4, 1.5), None);
+        assert_clip((0.6, -0.5, 1.5, 0.4), None);
+    }
+
+    #[test]
+    fn crossing_each_edge() {
+        assert_clip((-1.0, 0.5, 0.5, 0.5), Some((0.0, 0.5, 0.5, 0.5)));
+        assert_clip((0.5, 0.5, 2.0, 0.5), Some((0.5, 0.5, 1.0, 0.5)));
+        assert_clip((0.5, -1.0, 0.5, 0.5), Some((0.5, 0.0, 0.5, 0.5)));
+        assert_clip((0.5, 0.5, 0.5, 3.0), Some((0.5, 0.5, 0.5, 1.0)));
+        assert_clip((-0.5, 0.0, 0.5, 1.0), Some((0.0, 0.5, 0.5, 1.0)));
+        assert_clip((0.5, 0.0, 1.5, 1.0), Some((0.5, 0.0, 1.0, 0.5)));
+    }
+
+    #[test]
+    fn crossing_both_ends() {
+        assert_clip((-1.0, 0.25, 2.0, 0.25), Some((0.0, 0.25, 1.0, 0.25)));
+        assert_clip((0.75, 2.0, 0.75, -2.0), Some((0.75, 1.0, 0.75, 0.0)));
+        assert_clip((-0.5, -0.5, 1.5, 1.5), Some((0.0, 0.0, 1.0, 1.0)));
+        assert_clip((1.5, -0.5, -0.5, 1.5), Some((1.0, 0.0, 0.0, 1.0)));
+        assert_clip((-0.5, 0.25, 1.5, 0.75), Some((0.0, 0.375, 1.0, 0.625)));
+    }
+
+    #[test]
+    fn corners() {
+        // Touching a single corner
+        assert_clip((-0.5, 0.5, 0.5, 1.5), Some((0.0, 1.0, 0.0, 1.0)));
+        assert_clip((1.0, 1.0, 2.0, 2.0), Some((1.0, 1.0, 1.0, 1.0)));
+        // Cutting across one corner
+        assert_clip((0.75, -0.25, 1.25, 0.25), Some((1.0, 0.0, 1.0, 0.0)));
+        assert_clip((0.5, -0.25, 1.25, 0.5), Some((0.75, 0.0, 1.0, 0.25)));
+    }
+
+    #[test]
+    fn degenerate_points() {
+        assert_clip((0.5, 0.5, 0.5, 0.5), Some((0.5, 0.5, 0.5, 0.5)));
+        assert_clip((1.5, 0.5, 1.5, 0.5), None);
+    }
+
+    #[test]
+    fn hop_ranges_merge_runs() {
+        assert_eq!(hop_ranges(&[4, 5, 6, 7]), "4-7");
+        assert_eq!(hop_ranges(&[2, 4, 5, 5]), "2,4-5");
+        assert_eq!(hop_ranges(&[3]), "3");
+    }
+}